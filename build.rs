@@ -1,10 +1,13 @@
 extern crate winres;
 
 use std::env;
+use std::fmt::Write;
 use std::path::PathBuf;
 
 fn main() {
 
+    generate_opcode_table();
+
     let target = env::var("TARGET").unwrap();
     
     if target.contains("pc-windows") {
@@ -109,4 +112,112 @@ fn main() {
 
     }
 
-}
\ No newline at end of file
+}
+
+// Generates the opcode table shared by the CPU and the disassembler from tools/disassembler/opcodes.txt
+// Each line is tab separated: opcode, instruction, size in bytes, cycles (not taken/taken for conditional calls and returns)
+// and optionally a description; undocumented opcodes use '-' as their instruction
+fn generate_opcode_table() {
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=tools/disassembler/opcodes.txt");
+
+    let opcodes = std::fs::read_to_string("tools/disassembler/opcodes.txt").expect("Can't read opcodes.txt");
+
+    let pair_mnemonics = ["LXI", "DAD", "INX", "DCX", "PUSH", "POP", "STAX", "LDAX"];
+
+    let conditions = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+
+    let mut entries = vec![String::new(); 256];
+
+    for line in opcodes.lines().filter(|line| !line.trim().is_empty()) {
+
+        let columns: Vec<&str> = line.split('\t').collect();
+
+        let opcode = usize::from_str_radix(columns[0].trim_start_matches("0x"), 16).expect("Invalid opcode in opcodes.txt");
+
+        let instruction = columns[1].replace(',', " ");
+
+        let mut sections = instruction.split_whitespace();
+
+        let mnemonic = sections.next().expect("Missing instruction in opcodes.txt");
+
+        let mut operands = vec![];
+
+        for operand in sections {
+
+            operands.push(match operand {
+                "D8" if mnemonic == "IN" || mnemonic == "OUT" => "OperandKind::Port".to_string(),
+                "D8" => "OperandKind::Data8".to_string(),
+                "D16" => "OperandKind::Data16".to_string(),
+                "adr" => "OperandKind::Address".to_string(),
+                _ if mnemonic == "RST" => format!("OperandKind::Restart({})", operand),
+                _ if pair_mnemonics.contains(&mnemonic) => format!("OperandKind::RegisterPair(\"{}\")", operand),
+                _ => format!("OperandKind::Register(\"{}\")", operand),
+            });
+
+        }
+
+        let flow = match mnemonic {
+            "JMP" => "Jump",
+            "PCHL" => "IndirectJump",
+            "CALL" => "Call",
+            "RET" => "Return",
+            "RST" => "Restart",
+            "HLT" => "Halt",
+            _ if mnemonic.len() > 1 && conditions.contains(&&mnemonic[1..]) => match &mnemonic[..1] {
+                "J" => "ConditionalJump",
+                "C" => "ConditionalCall",
+                "R" => "ConditionalReturn",
+                _ => "Sequential",
+            },
+            _ => "Sequential",
+        };
+
+        let mut cycles = columns[3].split('/');
+
+        let base_cycles = cycles.next().expect("Missing cycles in opcodes.txt");
+
+        let branch_cycles = match cycles.next() {
+            Some(taken) => format!("Some({})", taken),
+            None => "None".to_string(),
+        };
+
+        entries[opcode] = if mnemonic == "-" {
+
+            format!("OpcodeInfo{{ mnemonic: \"DB\", operands: &[OperandKind::Opcode], length: 1, cycles: {}, branch_cycles: None, flow: Flow::Sequential }}, // 0x{:02x} - Undocumented", base_cycles, opcode)
+
+        }
+        else {
+
+            format!("OpcodeInfo{{ mnemonic: \"{}\", operands: &[{}], length: {}, cycles: {}, branch_cycles: {}, flow: Flow::{} }}, // 0x{:02x}",
+                mnemonic, operands.join(", "), columns[2], base_cycles, branch_cycles, flow, opcode
+            )
+
+        };
+
+    }
+
+    let mut output = String::from("pub static OPCODE_TABLE: [OpcodeInfo; 256] = [\n");
+
+    for (opcode, entry) in entries.iter().enumerate() {
+
+        if entry.is_empty() {
+
+            panic!("Opcode 0x{:02x} is missing from opcodes.txt", opcode);
+
+        }
+
+        writeln!(output, "    {}", entry).unwrap();
+
+    }
+
+    output += "];\n";
+
+    let mut output_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    output_path.push("opcodes.rs");
+
+    std::fs::write(output_path, output).expect("Can't write opcode table");
+
+}
@@ -0,0 +1,680 @@
+// Assembles Intel syntax 8080 source, e.g. listings written by the disassembler
+// Supports labels, ORG, DB, DW, DS, EQU, SET, END and every mnemonic in the opcode table
+
+mod expression;
+
+use crate::cpu::opcodes::{OperandKind, OPCODE_TABLE};
+use crate::intel_hex::Segment;
+
+use std::collections::HashMap;
+
+const MAXIMUM_PASSES: usize = 10;
+
+pub struct Program{
+    pub segments: Vec<Segment>, // Sorted by address
+    pub labels: Vec<(String, u16)>, // Labels in the order they are defined
+}
+
+impl Program{
+
+    // The bytes from the lowest to the highest address written, with gaps filled with 0
+    pub fn to_binary(&self) -> (u16, Vec<u8>){
+
+        let start = match self.segments.first() {
+
+            Some(segment) => segment.address,
+
+            None => return (0, vec![]),
+
+        };
+
+        let mut binary = vec![];
+
+        for segment in &self.segments{
+
+            let offset = (segment.address - start) as usize;
+
+            binary.resize(offset, 0);
+
+            binary.extend_from_slice(&segment.bytes);
+
+        }
+
+        (start, binary)
+
+    }
+
+}
+
+pub fn assemble(source: &str) -> Result<Program, String>{
+
+    let mut symbols = HashMap::new();
+
+    // Forward references are read as 0 until the symbols stop changing, which settles any ORG or DS that uses them
+    for _ in 0..MAXIMUM_PASSES{
+
+        let mut pass = Pass::new(symbols.clone(), false);
+
+        pass.run(source)?;
+
+        if pass.symbols == symbols{
+
+            break;
+
+        }
+
+        symbols = pass.symbols;
+
+    }
+
+    let mut pass = Pass::new(symbols, true);
+
+    pass.run(source)?;
+
+    pass.segments.sort_by_key(|segment| segment.address);
+
+    Ok(Program{
+        segments: pass.segments,
+        labels: pass.labels,
+    })
+
+}
+
+struct Pass{
+    symbols: HashMap<String, i32>, // Keyed by upper case name
+    previous_symbols: HashMap<String, i32>, // Values from the previous pass, used for forward references
+    defined: HashMap<String, bool>, // Names defined so far this pass, and whether they can be redefined with SET
+    address: u32,
+    line_address: u32, // Address at the start of the line, the value of $
+    final_pass: bool,
+    segments: Vec<Segment>,
+    written: Vec<bool>,
+    labels: Vec<(String, u16)>,
+}
+
+impl Pass{
+
+    fn new(previous_symbols: HashMap<String, i32>, final_pass: bool) -> Pass{
+
+        Pass{
+            symbols: HashMap::new(),
+            previous_symbols,
+            defined: HashMap::new(),
+            address: 0,
+            line_address: 0,
+            final_pass,
+            segments: vec![],
+            written: vec![false; 0x10000],
+            labels: vec![],
+        }
+
+    }
+
+    fn run(&mut self, source: &str) -> Result<(), String>{
+
+        for (line_index, line) in source.lines().enumerate(){
+
+            let finished = self.assemble_line(line).map_err(|error| format!("line {}: {}", line_index + 1, error))?;
+
+            if finished{
+
+                break;
+
+            }
+
+        }
+
+        Ok(())
+
+    }
+
+    // Returns true when END is reached
+    fn assemble_line(&mut self, line: &str) -> Result<bool, String>{
+
+        let line = strip_comment(line).trim();
+
+        self.line_address = self.address;
+
+        if line.is_empty(){
+
+            return Ok(false);
+
+        }
+
+        let (label, rest) = split_label(line);
+
+        let (operation, operands) = match rest.find(char::is_whitespace) {
+
+            Some(index) => (rest[..index].to_uppercase(), rest[index..].trim()),
+
+            None => (rest.to_uppercase(), ""),
+
+        };
+
+        // Names without a colon may be defined with EQU or SET, e.g. SCREEN EQU 2400H
+        if label.is_none(){
+
+            let mut words = operands.splitn(2, char::is_whitespace);
+
+            let directive = words.next().unwrap_or("").to_uppercase();
+
+            if (directive == "EQU" || directive == "SET") && !operation.is_empty(){
+
+                let name = rest.split_whitespace().next().unwrap_or("");
+
+                return self.define_constant(name, &directive, words.next().unwrap_or("")).map(|_| false);
+
+            }
+
+        }
+
+        if operation == "EQU" || operation == "SET"{
+
+            let name = label.ok_or_else(|| format!("{} requires a name", operation))?;
+
+            return self.define_constant(name, &operation, operands).map(|_| false);
+
+        }
+
+        if let Some(label) = label{
+
+            self.define(label, self.address as i32, false)?;
+
+            self.labels.push((label.to_string(), self.address as u16));
+
+        }
+
+        match operation.as_str() {
+
+            "" => {},
+
+            "ORG" => self.address = self.evaluate(operands)? as u16 as u32,
+
+            "END" => return Ok(true),
+
+            "DS" => {
+
+                let size = self.evaluate(operands)?;
+
+                if size < 0{
+
+                    return Err(format!("Invalid DS size {}", size));
+
+                }
+
+                self.advance(size as u32)?;
+
+            },
+
+            "DB" => {
+
+                for item in split_operands(operands){
+
+                    if is_string(item){
+
+                        let characters: Vec<char> = item.chars().collect();
+
+                        let (string, _) = expression::read_string(&characters, 0)?;
+
+                        for byte in string.bytes(){
+
+                            self.emit(byte)?;
+
+                        }
+
+                    }
+                    else{
+
+                        let value = self.evaluate_byte(item)?;
+
+                        self.emit(value)?;
+
+                    }
+
+                }
+
+            },
+
+            "DW" => {
+
+                for item in split_operands(operands){
+
+                    let value = self.evaluate_word(item)?;
+
+                    self.emit_word(value)?;
+
+                }
+
+            },
+
+            _ => self.assemble_instruction(&operation, operands)?,
+
+        }
+
+        Ok(false)
+
+    }
+
+    fn define_constant(&mut self, name: &str, directive: &str, operands: &str) -> Result<(), String>{
+
+        let value = self.evaluate(operands)?;
+
+        self.define(name, value, directive == "SET")
+
+    }
+
+    fn define(&mut self, name: &str, value: i32, redefinable: bool) -> Result<(), String>{
+
+        if !is_valid_name(name){
+
+            return Err(format!("Invalid name '{}'", name));
+
+        }
+
+        let key = name.to_uppercase();
+
+        match self.defined.get(&key) {
+
+            Some(true) if redefinable => {},
+
+            Some(_) => return Err(format!("'{}' is already defined", name)),
+
+            None => {},
+
+        }
+
+        self.defined.insert(key.clone(), redefinable);
+
+        self.symbols.insert(key, value);
+
+        Ok(())
+
+    }
+
+    fn assemble_instruction(&mut self, mnemonic: &str, operands: &str) -> Result<(), String>{
+
+        let operands = split_operands(operands);
+
+        let candidates: Vec<u8> = (0..=255u8)
+            .filter(|opcode| {
+
+                let info = &OPCODE_TABLE[*opcode as usize];
+
+                info.mnemonic == mnemonic
+                    && info.operands.len() == operands.len()
+                    && info.operands.iter().zip(&operands).all(|(kind, operand)| match kind {
+                        OperandKind::Register(name) | OperandKind::RegisterPair(name) => operand.eq_ignore_ascii_case(name),
+                        OperandKind::Opcode => false,
+                        _ => true,
+                    })
+
+            })
+            .collect();
+
+        let opcode = match candidates.as_slice() {
+
+            [] if OPCODE_TABLE.iter().any(|info| info.mnemonic == mnemonic) => return Err(format!("Invalid operands for {}", mnemonic)),
+
+            [] => return Err(format!("Unknown instruction '{}'", mnemonic)),
+
+            [opcode] => *opcode,
+
+            _ => {
+
+                // RST is the only mnemonic that is chosen by the value of an expression
+                let vector = self.evaluate(operands[0])?;
+
+                *candidates.iter()
+                    .find(|opcode| matches!(OPCODE_TABLE[**opcode as usize].operands[0], OperandKind::Restart(value) if value as i32 == vector))
+                    .ok_or_else(|| format!("Invalid restart vector {}", vector))?
+
+            },
+
+        };
+
+        self.emit(opcode)?;
+
+        for (kind, operand) in OPCODE_TABLE[opcode as usize].operands.iter().zip(&operands){
+
+            match kind {
+
+                OperandKind::Data8 | OperandKind::Port => {
+
+                    let value = self.evaluate_byte(operand)?;
+
+                    self.emit(value)?;
+
+                },
+
+                OperandKind::Data16 | OperandKind::Address => {
+
+                    let value = self.evaluate_word(operand)?;
+
+                    self.emit_word(value)?;
+
+                },
+
+                _ => {},
+
+            }
+
+        }
+
+        Ok(())
+
+    }
+
+    fn evaluate(&self, text: &str) -> Result<i32, String>{
+
+        let lookup = |name: &str| -> Result<i32, String>{
+
+            if name == "$"{
+
+                return Ok(self.line_address as i32);
+
+            }
+
+            match self.symbols.get(name).or_else(|| self.previous_symbols.get(name)) {
+
+                Some(value) => Ok(*value),
+
+                None if self.final_pass => Err(format!("Undefined symbol '{}'", name)),
+
+                None => Ok(0), // Defined later in the source
+
+            }
+
+        };
+
+        expression::evaluate(text, &lookup)
+
+    }
+
+    fn evaluate_byte(&self, text: &str) -> Result<u8, String>{
+
+        let value = self.evaluate(text)?;
+
+        if !(-256..=255).contains(&value){
+
+            return Err(format!("Value {} does not fit in a byte", value));
+
+        }
+
+        Ok(value as u8)
+
+    }
+
+    fn evaluate_word(&self, text: &str) -> Result<u16, String>{
+
+        let value = self.evaluate(text)?;
+
+        if !(-65536..=65535).contains(&value){
+
+            return Err(format!("Value {} does not fit in a word", value));
+
+        }
+
+        Ok(value as u16)
+
+    }
+
+    fn advance(&mut self, size: u32) -> Result<(), String>{
+
+        if self.address + size > 0x10000{
+
+            return Err("Output passes the end of the 64KiB address space".to_string());
+
+        }
+
+        self.address += size;
+
+        Ok(())
+
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<(), String>{
+
+        if self.final_pass{
+
+            if self.address >= 0x10000{
+
+                return Err("Output passes the end of the 64KiB address space".to_string());
+
+            }
+
+            let address = self.address as u16;
+
+            if self.written[address as usize]{
+
+                return Err(format!("Address {:04X} is written more than once", address));
+
+            }
+
+            self.written[address as usize] = true;
+
+            match self.segments.last_mut() {
+
+                Some(segment) if segment.address as u32 + segment.bytes.len() as u32 == self.address => segment.bytes.push(byte),
+
+                _ => self.segments.push(Segment{
+                    address,
+                    bytes: vec![byte],
+                }),
+
+            }
+
+        }
+
+        self.advance(1)
+
+    }
+
+    fn emit_word(&mut self, value: u16) -> Result<(), String>{
+
+        self.emit(value as u8)?;
+
+        self.emit((value >> 8) as u8)
+
+    }
+
+}
+
+// Removes a ; comment, ignoring semicolons inside quotes
+fn strip_comment(line: &str) -> &str{
+
+    let mut quote = None;
+
+    for (index, character) in line.char_indices(){
+
+        match (quote, character) {
+            (None, '\'') | (None, '"') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, ';') => return &line[..index],
+            _ => {},
+        }
+
+    }
+
+    line
+
+}
+
+// Splits off a leading "label:" if there is one
+fn split_label(line: &str) -> (Option<&str>, &str){
+
+    let end = line.find(|character: char| !expression::is_name_character(character)).unwrap_or(line.len());
+
+    if end > 0 && line[end..].starts_with(':'){
+
+        (Some(&line[..end]), line[end + 1..].trim())
+
+    }
+    else{
+
+        (None, line)
+
+    }
+
+}
+
+// Splits operands on commas that are not inside quotes or parentheses
+fn split_operands(operands: &str) -> Vec<&str>{
+
+    if operands.trim().is_empty(){
+
+        return vec![];
+
+    }
+
+    let mut items = vec![];
+
+    let mut quote = None;
+
+    let mut depth = 0;
+
+    let mut start = 0;
+
+    for (index, character) in operands.char_indices(){
+
+        match (quote, character) {
+            (None, '\'') | (None, '"') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(operands[start..index].trim());
+                start = index + 1;
+            },
+            _ => {},
+        }
+
+    }
+
+    items.push(operands[start..].trim());
+
+    items
+
+}
+
+// Whether a DB item is a single quoted string, rather than an expression such as 'A'+80H
+fn is_string(item: &str) -> bool{
+
+    let characters: Vec<char> = item.chars().collect();
+
+    match characters.first() {
+
+        Some('\'') | Some('"') => matches!(expression::read_string(&characters, 0), Ok((_, end)) if end == characters.len()),
+
+        _ => false,
+
+    }
+
+}
+
+fn is_valid_name(name: &str) -> bool{
+
+    name.starts_with(|character: char| !character.is_ascii_digit()) && name.chars().all(expression::is_name_character)
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::disassembler::{analysis, listing, Formatter};
+    use crate::cpu::disassembler::symbols::SymbolTable;
+
+    fn reassemble(rom: &[u8], origin: u16, lines: &[listing::Line]) -> Vec<u8>{
+
+        let formatter = Formatter{
+            labels: listing::generate_labels(lines),
+            ..Default::default()
+        };
+
+        let mut source = vec![];
+
+        listing::write_listing(rom, origin, lines, &formatter, &mut source).expect("Failed to write listing");
+
+        let program = assemble(&String::from_utf8(source).expect("Listing is not UTF-8")).expect("Failed to assemble listing");
+
+        let (start, binary) = program.to_binary();
+
+        assert_eq!(start, origin);
+
+        binary
+
+    }
+
+    // Every opcode, including undocumented ones, survives a disassemble and reassemble
+    #[test]
+    fn every_opcode_round_trips(){
+
+        let rom: Vec<u8> = (0..=255u8).flat_map(|opcode| vec![opcode, 0x34, 0x12]).collect();
+
+        assert_eq!(reassemble(&rom, 0x100, &listing::linear_sweep(&rom, 0x100)), rom);
+
+    }
+
+    #[test]
+    fn space_invaders_round_trips(){
+
+        let mut rom = vec![];
+
+        for name in ["h", "g", "f", "e"]{
+
+            let path = format!("{}/space-invaders-source/SpaceInvaders.{}", env!("CARGO_MANIFEST_DIR"), name);
+
+            rom.extend(std::fs::read(path).expect("Failed to read ROM"));
+
+        }
+
+        let code_map = analysis::trace(&rom, 0, &[0x00, 0x08, 0x10], &[], &SymbolTable::default(), None);
+
+        let lines = analysis::lines_from_code_map(&rom, &code_map, &[], &SymbolTable::default());
+
+        assert_eq!(reassemble(&rom, 0, &lines), rom);
+
+    }
+
+    #[test]
+    fn directives_and_expressions(){
+
+        let source = "
+            COUNT   EQU 3
+                    ORG 100H
+            START:  MVI A,COUNT*2+1     ; Comment with 'quotes; inside'
+                    LXI H,TABLE
+                    JMP $
+            TABLE:  DB 'A;B',0DH,'C'+80H,-1
+                    DW TABLE, HIGH TABLE SHL 1, NOT 0
+                    DS 2
+            AFTER:  RST COUNT+4
+                    END
+                    NOP
+        ";
+
+        let program = assemble(source).expect("Failed to assemble");
+
+        assert_eq!(program.segments, vec![
+            Segment{ address: 0x100, bytes: vec![0x3E, 0x07, 0x21, 0x08, 0x01, 0xC3, 0x05, 0x01, b'A', b';', b'B', 0x0D, 0xC3, 0xFF, 0x08, 0x01, 0x02, 0x00, 0xFF, 0xFF] },
+            Segment{ address: 0x116, bytes: vec![0xFF] },
+        ]);
+
+        assert_eq!(program.labels, vec![("START".to_string(), 0x100), ("TABLE".to_string(), 0x108), ("AFTER".to_string(), 0x116)]);
+
+    }
+
+    #[test]
+    fn errors_report_the_line(){
+
+        assert_eq!(assemble("NOP\nMOV A,Q").err(), Some("line 2: Invalid operands for MOV".to_string()));
+
+        assert_eq!(assemble("JMP NOWHERE").err(), Some("line 1: Undefined symbol 'NOWHERE'".to_string()));
+
+        assert_eq!(assemble("MVI A,100H").err(), Some("line 1: Value 256 does not fit in a byte".to_string()));
+
+        assert_eq!(assemble("X: NOP\nX: NOP").err(), Some("line 2: 'X' is already defined".to_string()));
+
+    }
+
+}
@@ -0,0 +1,400 @@
+// Evaluates assembler expressions, e.g. (TABLE+2*ENTRY) AND 0FFH
+// Operators from lowest to highest precedence: OR XOR, AND, NOT, + -, * / MOD SHL SHR, unary - + HIGH LOW
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token{
+    Number(i32),
+    Name(String), // A symbol, $ for the current address, or a word operator such as MOD
+    Symbol(char), // + - * / ( )
+}
+
+pub fn evaluate(text: &str, lookup: &dyn Fn(&str) -> Result<i32, String>) -> Result<i32, String>{
+
+    let tokens = tokenize(text)?;
+
+    if tokens.is_empty(){
+
+        return Err("Missing expression".to_string());
+
+    }
+
+    let mut parser = Parser{
+        tokens,
+        position: 0,
+        lookup,
+    };
+
+    let value = parser.parse_or()?;
+
+    match parser.tokens.get(parser.position) {
+
+        Some(token) => Err(format!("Unexpected {} in expression '{}'", describe(token), text.trim())),
+
+        None => Ok(value),
+
+    }
+
+}
+
+// Whether a character may appear in a symbol name
+pub fn is_name_character(character: char) -> bool{
+
+    character.is_ascii_alphanumeric() || character == '_' || character == '?' || character == '@'
+
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String>{
+
+    let mut tokens = vec![];
+
+    let characters: Vec<char> = text.chars().collect();
+
+    let mut index = 0;
+
+    while index < characters.len(){
+
+        let character = characters[index];
+
+        if character.is_whitespace(){
+
+            index += 1;
+
+        }
+        else if character == '\'' || character == '"'{
+
+            let (string, end) = read_string(&characters, index)?;
+
+            let bytes = string.as_bytes();
+
+            let value = match bytes.len() {
+                1 => bytes[0] as i32,
+                2 => ((bytes[0] as i32) << 8) | bytes[1] as i32,
+                _ => return Err(format!("Character constant {} must be 1 or 2 characters long", &text[index..end])),
+            };
+
+            tokens.push(Token::Number(value));
+
+            index = end;
+
+        }
+        else if is_name_character(character) || character == '$'{
+
+            let start = index;
+
+            while index < characters.len() && (is_name_character(characters[index]) || (characters[index] == '$' && index == start)){
+
+                index += 1;
+
+            }
+
+            let word: String = characters[start..index].iter().collect();
+
+            if character.is_ascii_digit(){
+
+                tokens.push(Token::Number(parse_number(&word)?));
+
+            }
+            else{
+
+                tokens.push(Token::Name(word.to_uppercase()));
+
+            }
+
+        }
+        else if "+-*/()".contains(character){
+
+            tokens.push(Token::Symbol(character));
+
+            index += 1;
+
+        }
+        else{
+
+            return Err(format!("Unexpected character '{}' in expression", character));
+
+        }
+
+    }
+
+    Ok(tokens)
+
+}
+
+// Reads a quoted string starting at the given index, a doubled quote stands for the quote itself
+// Returns the string and the index after the closing quote
+pub fn read_string(characters: &[char], start: usize) -> Result<(String, usize), String>{
+
+    let quote = characters[start];
+
+    let mut string = String::new();
+
+    let mut index = start + 1;
+
+    loop {
+
+        match characters.get(index) {
+
+            Some(character) if *character == quote => {
+
+                if characters.get(index + 1) == Some(&quote){
+
+                    string.push(quote);
+
+                    index += 2;
+
+                }
+                else{
+
+                    return Ok((string, index + 1));
+
+                }
+
+            },
+
+            Some(character) => {
+
+                string.push(*character);
+
+                index += 1;
+
+            },
+
+            None => return Err("Unterminated string".to_string()),
+
+        }
+
+    }
+
+}
+
+// Numbers are decimal unless they have a suffix: H hexadecimal, B binary, O or Q octal, D decimal
+// A 0x prefix is also accepted for hexadecimal
+fn parse_number(word: &str) -> Result<i32, String>{
+
+    let upper = word.to_uppercase();
+
+    let (digits, radix) = if let Some(digits) = upper.strip_prefix("0X") {
+        (digits, 16)
+    }
+    else if let Some(digits) = upper.strip_suffix('H') {
+        (digits, 16)
+    }
+    else if let Some(digits) = upper.strip_suffix('B') {
+        (digits, 2)
+    }
+    else if let Some(digits) = upper.strip_suffix('O').or_else(|| upper.strip_suffix('Q')) {
+        (digits, 8)
+    }
+    else if let Some(digits) = upper.strip_suffix('D') {
+        (digits, 10)
+    }
+    else{
+        (upper.as_str(), 10)
+    };
+
+    match i64::from_str_radix(digits, radix) {
+
+        Ok(value) if value <= 0xFFFF => Ok(value as i32),
+
+        Ok(_) => Err(format!("Number '{}' does not fit in 16 bits", word)),
+
+        Err(_) => Err(format!("Invalid number '{}'", word)),
+
+    }
+
+}
+
+fn describe(token: &Token) -> String{
+
+    match token {
+        Token::Number(value) => format!("number {}", value),
+        Token::Name(name) => format!("'{}'", name),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+    }
+
+}
+
+struct Parser<'a>{
+    tokens: Vec<Token>,
+    position: usize,
+    lookup: &'a dyn Fn(&str) -> Result<i32, String>,
+}
+
+impl Parser<'_>{
+
+    fn peek_name(&self, names: &[&str]) -> Option<String>{
+
+        match self.tokens.get(self.position) {
+            Some(Token::Name(name)) if names.contains(&name.as_str()) => Some(name.clone()),
+            _ => None,
+        }
+
+    }
+
+    fn peek_symbol(&self, symbols: &str) -> Option<char>{
+
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(symbol)) if symbols.contains(*symbol) => Some(*symbol),
+            _ => None,
+        }
+
+    }
+
+    fn parse_or(&mut self) -> Result<i32, String>{
+
+        let mut value = self.parse_and()?;
+
+        while let Some(operator) = self.peek_name(&["OR", "XOR"]){
+
+            self.position += 1;
+
+            let right = self.parse_and()?;
+
+            value = if operator == "OR" { value | right } else { value ^ right };
+
+        }
+
+        Ok(value)
+
+    }
+
+    fn parse_and(&mut self) -> Result<i32, String>{
+
+        let mut value = self.parse_not()?;
+
+        while self.peek_name(&["AND"]).is_some(){
+
+            self.position += 1;
+
+            value &= self.parse_not()?;
+
+        }
+
+        Ok(value)
+
+    }
+
+    fn parse_not(&mut self) -> Result<i32, String>{
+
+        if self.peek_name(&["NOT"]).is_some(){
+
+            self.position += 1;
+
+            return Ok(!self.parse_not()? & 0xFFFF);
+
+        }
+
+        self.parse_additive()
+
+    }
+
+    fn parse_additive(&mut self) -> Result<i32, String>{
+
+        let mut value = self.parse_multiplicative()?;
+
+        while let Some(operator) = self.peek_symbol("+-"){
+
+            self.position += 1;
+
+            let right = self.parse_multiplicative()?;
+
+            value = if operator == '+' { value.wrapping_add(right) } else { value.wrapping_sub(right) };
+
+        }
+
+        Ok(value)
+
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<i32, String>{
+
+        let mut value = self.parse_unary()?;
+
+        loop {
+
+            let operator = match (self.peek_symbol("*/"), self.peek_name(&["MOD", "SHL", "SHR"])) {
+                (Some(symbol), _) => symbol.to_string(),
+                (None, Some(name)) => name,
+                (None, None) => break,
+            };
+
+            self.position += 1;
+
+            let right = self.parse_unary()?;
+
+            value = match operator.as_str() {
+                "*" => value.wrapping_mul(right),
+                "/" | "MOD" if right == 0 => return Err("Division by zero".to_string()),
+                "/" => value / right,
+                "MOD" => value % right,
+                "SHL" => value.wrapping_shl(right as u32) & 0xFFFF,
+                _ => (value & 0xFFFF).wrapping_shr(right as u32),
+            };
+
+        }
+
+        Ok(value)
+
+    }
+
+    fn parse_unary(&mut self) -> Result<i32, String>{
+
+        if let Some(operator) = self.peek_symbol("+-"){
+
+            self.position += 1;
+
+            let value = self.parse_unary()?;
+
+            return Ok(if operator == '-' { value.wrapping_neg() } else { value });
+
+        }
+
+        if let Some(operator) = self.peek_name(&["HIGH", "LOW"]){
+
+            self.position += 1;
+
+            let value = self.parse_unary()?;
+
+            return Ok(if operator == "HIGH" { (value >> 8) & 0xFF } else { value & 0xFF });
+
+        }
+
+        self.parse_primary()
+
+    }
+
+    fn parse_primary(&mut self) -> Result<i32, String>{
+
+        let token = self.tokens.get(self.position).cloned().ok_or_else(|| "Unexpected end of expression".to_string())?;
+
+        self.position += 1;
+
+        match token {
+
+            Token::Number(value) => Ok(value),
+
+            Token::Symbol('(') => {
+
+                let value = self.parse_or()?;
+
+                if self.peek_symbol(")").is_none(){
+
+                    return Err("Missing ')' in expression".to_string());
+
+                }
+
+                self.position += 1;
+
+                Ok(value)
+
+            },
+
+            Token::Name(name) => (self.lookup)(&name),
+
+            token => Err(format!("Unexpected {} in expression", describe(&token))),
+
+        }
+
+    }
+
+}
@@ -0,0 +1,307 @@
+// Runs CP/M 2.2 programs (.COM files) by trapping calls to the BDOS and BIOS and carrying them out on the host
+// Memory holds the page zero a program expects, the program at 0100H and a BDOS and BIOS made of RET instructions:
+// when the program counter reaches one of them the call is handled here before the RET returns to the program
+mod bdos;
+mod console;
+mod fcb;
+
+pub use console::Console;
+#[cfg(test)]
+pub use console::Capture;
+
+use bdos::Bdos;
+use crate::cpu::Processor8080;
+use crate::machine::AudioController;
+
+use std::path::PathBuf;
+
+const WARM_BOOT: usize = 0x0000; // Holds a jump to the warm boot entry of the BIOS
+const IOBYTE: usize = 0x0003;
+const BDOS_CALL: usize = 0x0005; // Holds a jump to the BDOS, whose address also gives the top of the memory programs can use
+const FCBS: [usize; 2] = [0x005C, 0x006C]; // Filled from the first two command line arguments
+const DEFAULT_DMA: u16 = 0x0080; // Also holds the command line, as a length followed by the text
+const PROGRAM_ADDRESS: usize = 0x0100;
+const STACK_ADDRESS: u16 = 0xFC00;
+const BDOS_ADDRESS: u16 = 0xFC06;
+const BIOS_ADDRESS: u16 = 0xFE00; // A table of 17 entry points, three bytes apart
+const BIOS_FUNCTIONS: u16 = 17;
+const DISK_PARAMETERS: u16 = 0xFE40; // For programs that ask for the disk layout, that of an 8" single density disk
+const ALLOCATION_VECTOR: u16 = 0xFE50; // Every block free
+
+const DISK_PARAMETER_BLOCK: [u8; 15] = [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xC0, 0x00, 16, 0, 2, 0];
+
+// Why a run ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit{
+    WarmBoot, // The program finished by jumping to 0000H or calling BDOS function 0
+    InputEnded, // The program waited for console input after the last character
+    CycleLimit,
+}
+
+pub struct Cpm{
+    pub processor: Processor8080,
+    bdos: Bdos,
+}
+
+impl Cpm{
+
+    // Loads the program with the arguments as its command line, files are found in the directory
+    pub fn new(program: &[u8], arguments: &[String], directory: PathBuf, console: Console) -> Result<Self, String>{
+
+        if program.len() > STACK_ADDRESS as usize - PROGRAM_ADDRESS{
+
+            return Err(format!("The program is {} bytes, larger than the {} bytes of memory available", program.len(), STACK_ADDRESS as usize - PROGRAM_ADDRESS));
+
+        }
+
+        let mut memory = vec![0; 0x10000];
+
+        let bios_warm_boot = BIOS_ADDRESS + 3;
+
+        memory[WARM_BOOT..WARM_BOOT + 3].copy_from_slice(&[0xC3, bios_warm_boot as u8, (bios_warm_boot >> 8) as u8]); // JMP
+
+        memory[BDOS_CALL..BDOS_CALL + 3].copy_from_slice(&[0xC3, BDOS_ADDRESS as u8, (BDOS_ADDRESS >> 8) as u8]);
+
+        memory[BDOS_ADDRESS as usize] = 0xC9; // RET
+
+        for function in 0..BIOS_FUNCTIONS{
+
+            memory[(BIOS_ADDRESS + function * 3) as usize] = 0xC9;
+
+        }
+
+        memory[DISK_PARAMETERS as usize..DISK_PARAMETERS as usize + DISK_PARAMETER_BLOCK.len()].copy_from_slice(&DISK_PARAMETER_BLOCK);
+
+        for (argument, address) in arguments.iter().zip(FCBS){
+
+            fcb::parse(argument, &mut memory[address..address + 16]);
+
+        }
+
+        for address in FCBS.iter().skip(arguments.len()){
+
+            memory[address + 1..address + 12].fill(b' ');
+
+        }
+
+        let command_line: Vec<u8> = arguments.iter().flat_map(|argument| format!(" {}", argument.to_ascii_uppercase()).into_bytes()).take(127).collect();
+
+        memory[DEFAULT_DMA as usize] = command_line.len() as u8;
+
+        memory[DEFAULT_DMA as usize + 1..DEFAULT_DMA as usize + 1 + command_line.len()].copy_from_slice(&command_line);
+
+        memory[PROGRAM_ADDRESS..PROGRAM_ADDRESS + program.len()].copy_from_slice(program);
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        processor.memory = memory;
+
+        let mut registers = processor.registers();
+
+        registers.stack_pointer = STACK_ADDRESS - 2; // Holds 0000H, so returning from the program is a warm boot
+
+        registers.program_counter = PROGRAM_ADDRESS as u16;
+
+        processor.set_registers(&registers);
+
+        Ok(Cpm{
+            processor,
+            bdos: Bdos::new(directory, console),
+        })
+
+    }
+
+    pub fn run(&mut self, cycle_limit: Option<u64>) -> Exit{
+
+        let mut ports = vec![0; 256];
+
+        let mut audio_controller = AudioController::default();
+
+        let exit = loop {
+
+            let program_counter = self.processor.registers().program_counter;
+
+            let trapped = match program_counter {
+
+                BDOS_ADDRESS => self.bdos.call(&mut self.processor),
+
+                _ if (BIOS_ADDRESS..BIOS_ADDRESS + BIOS_FUNCTIONS * 3).contains(&program_counter) && (program_counter - BIOS_ADDRESS).is_multiple_of(3) => {
+
+                    self.bios((program_counter - BIOS_ADDRESS) / 3)
+
+                },
+
+                _ => None,
+
+            };
+
+            if let Some(exit) = trapped{
+
+                break exit;
+
+            }
+
+            if cycle_limit.is_some_and(|limit| self.processor.total_cycles >= limit){
+
+                break Exit::CycleLimit;
+
+            }
+
+            self.processor.emulate(&mut ports, &mut audio_controller);
+
+            self.processor.cycles_elapsed = 0; // Only counted per frame by the machines, and would overflow here
+
+        };
+
+        self.bdos.console.flush();
+
+        exit
+
+    }
+
+    // There are no disks to access directly, so the disk functions fail and programs have to go through the BDOS
+    fn bios(&mut self, function: u16) -> Option<Exit>{
+
+        let mut registers = self.processor.registers();
+
+        let console = &mut self.bdos.console;
+
+        match function {
+
+            0 | 1 => return Some(Exit::WarmBoot), // BOOT, WBOOT
+
+            2 => registers.a = if console.status() { 0xFF } else { 0 }, // CONST
+
+            3 => registers.a = match console.read() { // CONIN
+                Some(byte) => byte,
+                None => return Some(Exit::InputEnded),
+            },
+
+            4 | 5 => console.write(registers.c), // CONOUT, LIST
+
+            7 => registers.a = 0x1A, // READER
+
+            9 => { // SELDSK, no disk parameter header
+
+                registers.h = 0;
+                registers.l = 0;
+
+            },
+
+            13 | 14 => registers.a = 1, // READ, WRITE
+
+            15 => registers.a = 0xFF, // LISTST
+
+            16 => { // SECTRAN, sectors are not skewed
+
+                registers.h = registers.b;
+                registers.l = registers.c;
+
+            },
+
+            _ => {}, // PUNCH, HOME, SETTRK, SETSEC, SETDMA
+
+        }
+
+        self.processor.set_registers(&registers);
+
+        None
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn console_and_files(){
+
+        let source = "
+                    ORG 0100H
+            BDOS    EQU 0005H
+            FCB     EQU 005CH
+                    MVI C,10        ; Read a line into the record to write
+                    LXI D,BUFFER
+                    CALL BDOS
+                    MVI C,22        ; Make the file named on the command line
+                    LXI D,FCB
+                    CALL BDOS
+                    MVI C,26
+                    LXI D,RECORD
+                    CALL BDOS
+                    MVI C,21
+                    LXI D,FCB
+                    CALL BDOS
+                    MVI C,16
+                    LXI D,FCB
+                    CALL BDOS
+                    XRA A
+                    STA FCB+32
+                    MVI C,15        ; Open it again and read it back to 0080H
+                    LXI D,FCB
+                    CALL BDOS
+                    MVI C,26
+                    LXI D,0080H
+                    CALL BDOS
+                    CALL READ
+                    CALL READ       ; Past the end of the file
+                    MVI A,'$'
+                    STA 0085H
+                    MVI C,9
+                    LXI D,0080H
+                    CALL BDOS
+                    JMP 0
+            READ:   MVI C,20
+                    LXI D,FCB
+                    CALL BDOS
+                    ADI '0'         ; Print the result
+                    MOV E,A
+                    MVI C,2
+                    JMP BDOS
+            BUFFER: DB 127,0
+            RECORD: DS 128
+        ";
+
+        let (_, binary) = assemble(source).expect("Failed to assemble").to_binary();
+
+        let directory = std::env::temp_dir().join(format!("emulator_8080_cpm_{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).expect("Failed to create directory");
+
+        let (sender, input) = mpsc::channel();
+
+        for byte in b"hello\r"{
+
+            sender.send(*byte).expect("Failed to send input");
+
+        }
+
+        let output = Capture::default();
+
+        let mut cpm = Cpm::new(&binary, &["test.txt".to_string()], directory.clone(), Console::new(input, Box::new(output.clone()), true)).expect("Failed to load program");
+
+        let exit = cpm.run(Some(1_000_000));
+
+        let file = std::fs::read(directory.join("TEST.TXT"));
+
+        std::fs::remove_dir_all(&directory).expect("Failed to remove directory");
+
+        assert_eq!(exit, Exit::WarmBoot);
+
+        assert_eq!(output.text(), "hello\r01hello");
+
+        let file = file.expect("Failed to read the file written");
+
+        assert_eq!(file.len(), 128);
+
+        assert!(file.starts_with(b"hello"));
+
+    }
+
+}
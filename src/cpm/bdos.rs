@@ -0,0 +1,526 @@
+// The BDOS functions of CP/M 2.2, called with the function number in C and a byte in E or an address in DE
+// Results are returned in A and HL, with B holding the same as H, as the real BDOS does
+// Files are host files in one directory, named in 8.3 form and matched regardless of case, and every drive refers to it
+// Nothing is kept open between calls: the FCB holds the position, so reads and writes open the host file each time
+use super::console::Console;
+use super::fcb::{self, Name};
+use super::{Exit, ALLOCATION_VECTOR, DEFAULT_DMA, DISK_PARAMETERS, IOBYTE};
+use crate::cpu::Processor8080;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub struct Bdos{
+    pub console: Console,
+    directory: PathBuf,
+    dma: u16, // Where records are read to and written from
+    drive: u8,
+    user: u8,
+    search: Vec<(Name, u32)>, // Files still to be returned by search next, last first, with their sizes in records
+}
+
+impl Bdos{
+
+    pub fn new(directory: PathBuf, console: Console) -> Self{
+
+        Bdos{
+            console,
+            directory,
+            dma: DEFAULT_DMA,
+            drive: 0,
+            user: 0,
+            search: vec![],
+        }
+
+    }
+
+    // Carries out the function the program called, leaving the RET at the BDOS entry point to return to it
+    pub fn call(&mut self, processor: &mut Processor8080) -> Option<Exit>{
+
+        let mut registers = processor.registers();
+
+        let parameter = ((registers.d as u16) << 8) | registers.e as u16;
+
+        let memory = &mut processor.memory;
+
+        let result = match registers.c {
+
+            0 => return Some(Exit::WarmBoot), // System reset
+
+            1 => { // Console input
+
+                let Some(byte) = self.console.read() else { return Some(Exit::InputEnded) };
+
+                if byte.is_ascii_graphic() || b" \r\n\t\x08".contains(&byte){
+
+                    self.console.echo(&[byte]);
+
+                }
+
+                byte as u16
+
+            },
+
+            2 | 5 => { // Console output, list output
+
+                self.console.write(registers.e);
+
+                0
+
+            },
+
+            3 => 0x1A, // Reader input, always at the end of the file
+
+            4 => 0, // Punch output
+
+            6 => match registers.e { // Direct console I/O
+
+                0xFF => match self.console.poll() {
+                    Some(byte) => byte as u16,
+                    None if self.console.ended() => return Some(Exit::InputEnded), // A program polling for input would never finish
+                    None => 0,
+                },
+
+                0xFE => self.status(),
+
+                byte => {
+
+                    self.console.write(byte);
+
+                    0
+
+                },
+
+            },
+
+            7 => memory[IOBYTE] as u16,
+
+            8 => {
+
+                memory[IOBYTE] = registers.e;
+
+                0
+
+            },
+
+            9 => { // Print string, terminated by $
+
+                let mut address = parameter;
+
+                while memory[address as usize] != b'$'{
+
+                    self.console.write(memory[address as usize]);
+
+                    address = address.wrapping_add(1);
+
+                }
+
+                0
+
+            },
+
+            10 => { // Read console buffer
+
+                if self.read_line(memory, parameter).is_none(){
+
+                    return Some(Exit::InputEnded);
+
+                }
+
+                0
+
+            },
+
+            11 => self.status(),
+
+            12 => 0x0022, // Version 2.2
+
+            13 => { // Reset disk system
+
+                self.dma = DEFAULT_DMA;
+
+                self.drive = 0;
+
+                0
+
+            },
+
+            14 => { // Select disk
+
+                self.drive = registers.e & 0x0F;
+
+                0
+
+            },
+
+            18 => self.search_next(memory),
+
+            24 => 1 << self.drive, // Login vector
+
+            25 => self.drive as u16,
+
+            26 => { // Set DMA address
+
+                self.dma = parameter;
+
+                0
+
+            },
+
+            27 => ALLOCATION_VECTOR,
+
+            28 | 29 => 0, // Write protect disk, read only vector
+
+            31 => DISK_PARAMETERS,
+
+            32 => { // Get or set user code
+
+                if registers.e == 0xFF{
+
+                    self.user as u16
+
+                }
+                else{
+
+                    self.user = registers.e & 0x0F;
+
+                    0
+
+                }
+
+            },
+
+            function @ (15..=17 | 19..=23 | 30 | 33..=36 | 40) => {
+
+                let mut block = read_memory(memory, parameter, fcb::SIZE);
+
+                let result = self.file(function, &mut block, memory);
+
+                write_memory(memory, parameter, &block);
+
+                result
+
+            },
+
+            _ => 0, // Reset drive, and functions that don't exist in CP/M 2.2
+
+        };
+
+        registers.l = result as u8;
+        registers.a = registers.l;
+        registers.h = (result >> 8) as u8;
+        registers.b = registers.h;
+
+        processor.set_registers(&registers);
+
+        None
+
+    }
+
+    fn status(&mut self) -> u16{
+
+        if self.console.status() { 0xFF } else { 0 }
+
+    }
+
+    // Reads a line into the buffer at the address, which holds the maximum length followed by the length read and the line
+    // Returns None if the input ended first
+    fn read_line(&mut self, memory: &mut [u8], address: u16) -> Option<()>{
+
+        let maximum = memory[address as usize] as usize;
+
+        let mut line = vec![];
+
+        while line.len() < maximum{
+
+            match self.console.read()? {
+
+                b'\r' | b'\n' => {
+
+                    self.console.echo(b"\r");
+
+                    break;
+
+                },
+
+                0x08 | 0x7F => {
+
+                    if line.pop().is_some(){
+
+                        self.console.echo(b"\x08 \x08");
+
+                    }
+
+                },
+
+                byte => {
+
+                    self.console.echo(&[byte]);
+
+                    line.push(byte);
+
+                },
+
+            }
+
+        }
+
+        write_memory(memory, address.wrapping_add(1), &[line.len() as u8]);
+
+        write_memory(memory, address.wrapping_add(2), &line);
+
+        Some(())
+
+    }
+
+    // File functions, given the FCB to update
+    fn file(&mut self, function: u8, fcb: &mut [u8], memory: &mut [u8]) -> u16{
+
+        match function {
+
+            15 => match self.find(&fcb::name(fcb)).into_iter().next() { // Open file
+
+                Some((name, path)) => {
+
+                    fcb::set_name(fcb, &name);
+
+                    fcb::set_record_count(fcb, records(&path));
+
+                    0
+
+                },
+
+                None => 0xFF,
+
+            },
+
+            16 | 30 => if self.path(fcb).is_some() { 0 } else { 0xFF }, // Close file, set file attributes
+
+            17 => { // Search first, where a drive of ? matches every file
+
+                let pattern = if fcb[0] == b'?' { [b'?'; 11] } else { fcb::name(fcb) };
+
+                self.search = self.find(&pattern).into_iter().rev().map(|(name, path)| (name, records(&path))).collect();
+
+                self.search_next(memory)
+
+            },
+
+            19 => { // Delete file
+
+                let deleted = self.find(&fcb::name(fcb)).into_iter().filter(|(_, path)| fs::remove_file(path).is_ok()).count();
+
+                if deleted > 0 { 0 } else { 0xFF }
+
+            },
+
+            20 | 21 | 33 | 34 | 40 => { // Read and write sequential, read and write random, write random with zero fill
+
+                let Some(path) = self.path(fcb) else { return 0xFF };
+
+                let sequential = function == 20 || function == 21;
+
+                let record = if sequential { fcb::sequential_record(fcb) } else { fcb::random_record(fcb) };
+
+                let result = match function {
+                    20 | 33 => self.read_record(&path, record, memory),
+                    _ => self.write_record(&path, record, memory),
+                };
+
+                // Random access leaves the sequential position at the record, so reading on carries on from it
+                fcb::set_sequential_record(fcb, if sequential && result == 0 { record + 1 } else { record });
+
+                fcb::set_record_count(fcb, records(&path));
+
+                result
+
+            },
+
+            22 => { // Make file
+
+                let name = fcb::name(fcb);
+
+                if name.contains(&b'?'){
+
+                    return 0xFF;
+
+                }
+
+                let path = self.path(fcb).unwrap_or_else(|| self.directory.join(fcb::to_host(&name)));
+
+                match File::create(path) {
+
+                    Ok(_) => {
+
+                        fcb::set_record_count(fcb, 0);
+
+                        0
+
+                    },
+
+                    Err(_) => 0xFF,
+
+                }
+
+            },
+
+            23 => { // Rename file
+
+                let new_name = fcb::new_name(fcb);
+
+                match self.path(fcb) {
+                    Some(path) if !new_name.contains(&b'?') && fs::rename(&path, self.directory.join(fcb::to_host(&new_name))).is_ok() => 0,
+                    _ => 0xFF,
+                }
+
+            },
+
+            35 => match self.path(fcb) { // Compute file size
+
+                Some(path) => {
+
+                    fcb::set_random_record(fcb, records(&path));
+
+                    0
+
+                },
+
+                None => 0xFF,
+
+            },
+
+            36 => { // Set random record
+
+                fcb::set_random_record(fcb, fcb::sequential_record(fcb));
+
+                0
+
+            },
+
+            _ => 0,
+
+        }
+
+    }
+
+    // Writes the directory entry of the next file found to the DMA buffer
+    fn search_next(&mut self, memory: &mut [u8]) -> u16{
+
+        match self.search.pop() {
+
+            Some((name, file_records)) => {
+
+                write_memory(memory, self.dma, &fcb::directory_entry(&name, file_records));
+
+                0 // The entry is the first of the four in the buffer
+
+            },
+
+            None => 0xFF,
+
+        }
+
+    }
+
+    // Returns 1 if the record is past the end of the file, a record the file ends part way through is padded with 1AH
+    fn read_record(&self, path: &Path, record: u32, memory: &mut [u8]) -> u16{
+
+        let mut bytes = vec![];
+
+        let read = File::open(path).and_then(|mut file| {
+
+            file.seek(SeekFrom::Start(record as u64 * fcb::RECORD_SIZE as u64))?;
+
+            file.take(fcb::RECORD_SIZE as u64).read_to_end(&mut bytes)
+
+        });
+
+        match read {
+
+            Ok(0) | Err(_) => 1,
+
+            Ok(_) => {
+
+                bytes.resize(fcb::RECORD_SIZE, 0x1A);
+
+                write_memory(memory, self.dma, &bytes);
+
+                0
+
+            },
+
+        }
+
+    }
+
+    // Returns 2, disk full, if the host file can't be written
+    fn write_record(&self, path: &Path, record: u32, memory: &[u8]) -> u16{
+
+        let written = OpenOptions::new().write(true).open(path).and_then(|mut file| {
+
+            file.seek(SeekFrom::Start(record as u64 * fcb::RECORD_SIZE as u64))?;
+
+            file.write_all(&read_memory(memory, self.dma, fcb::RECORD_SIZE))
+
+        });
+
+        if written.is_ok() { 0 } else { 2 }
+
+    }
+
+    // Host files in the directory with names matching the pattern, sorted so searches always return them in the same order
+    fn find(&self, pattern: &Name) -> Vec<(Name, PathBuf)>{
+
+        let Ok(entries) = fs::read_dir(&self.directory) else { return vec![] };
+
+        let mut found: Vec<(Name, PathBuf)> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .filter_map(|entry| {
+
+                let name = fcb::from_host(entry.file_name().to_str()?)?;
+
+                fcb::matches(pattern, &name).then(|| (name, entry.path()))
+
+            })
+            .collect();
+
+        found.sort();
+
+        found
+
+    }
+
+    fn path(&self, fcb: &[u8]) -> Option<PathBuf>{
+
+        self.find(&fcb::name(fcb)).into_iter().next().map(|(_, path)| path)
+
+    }
+
+}
+
+fn records(path: &Path) -> u32{
+
+    let length = fs::metadata(path).map_or(0, |metadata| metadata.len());
+
+    length.div_ceil(fcb::RECORD_SIZE as u64) as u32
+
+}
+
+// Memory is 64K, so reads and writes wrap around the top of it
+fn read_memory(memory: &[u8], address: u16, length: usize) -> Vec<u8>{
+
+    (0..length).map(|offset| memory[address.wrapping_add(offset as u16) as usize]).collect()
+
+}
+
+fn write_memory(memory: &mut [u8], address: u16, bytes: &[u8]){
+
+    for (offset, byte) in bytes.iter().enumerate(){
+
+        memory[address.wrapping_add(offset as u16) as usize] = *byte;
+
+    }
+
+}
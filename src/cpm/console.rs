@@ -0,0 +1,363 @@
+// The console of a CP/M program, with input taken from a channel so it can be polled without waiting for a key
+// It can also be a serial terminal served over TCP, for telnet clients and test scripts
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+pub struct Console{
+    input: Receiver<u8>,
+    pending: Option<u8>, // Seen by a status check and not yet read
+    ended: bool, // Nothing more will arrive once the pending character is read
+    output: Box<dyn Write>,
+    echo: bool, // False when a terminal has already echoed what was typed
+}
+
+impl Console{
+
+    pub fn new(input: Receiver<u8>, output: Box<dyn Write>, echo: bool) -> Self{
+
+        Console{
+            input,
+            pending: None,
+            ended: false,
+            output,
+            echo,
+        }
+
+    }
+
+    // Reads standard input on its own thread, with line endings turned into the carriage return CP/M programs expect
+    pub fn standard() -> Self{
+
+        let (sender, receiver) = mpsc::channel();
+
+        let echo = !io::stdin().is_terminal();
+
+        std::thread::spawn(move || {
+
+            for byte in io::stdin().lock().bytes().map_while(Result::ok){
+
+                let byte = match byte {
+                    b'\r' => continue, // Windows line endings
+                    b'\n' => b'\r',
+                    _ => byte,
+                };
+
+                if sender.send(byte).is_err(){
+
+                    break;
+
+                }
+
+            }
+
+        });
+
+        Console::new(receiver, Box::new(io::stdout()), echo)
+
+    }
+
+    // Serves the console to one client at a time, later clients can connect once the last has gone
+    // Input ends only when the listener fails, and output is dropped while no client is connected
+    pub fn listen(listener: TcpListener) -> Self{
+
+        let (sender, receiver) = mpsc::channel();
+
+        let client = Arc::new(Mutex::new(None));
+
+        let connection = Connection{ client: Arc::clone(&client) };
+
+        std::thread::spawn(move || {
+
+            for stream in listener.incoming(){
+
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+
+                let _ = stream.set_nodelay(true); // Characters are sent one at a time
+
+                *client.lock().expect("Failed to lock serial connection") = stream.try_clone().ok();
+
+                if !receive(stream, &sender){
+
+                    break;
+
+                }
+
+                *client.lock().expect("Failed to lock serial connection") = None;
+
+            }
+
+        });
+
+        Console::new(receiver, Box::new(connection), false) // Telnet clients echo locally
+
+    }
+
+    // Whether a character is waiting to be read
+    pub fn status(&mut self) -> bool{
+
+        self.flush();
+
+        if self.pending.is_none(){
+
+            match self.input.try_recv() {
+                Ok(byte) => self.pending = Some(byte),
+                Err(TryRecvError::Disconnected) => self.ended = true,
+                Err(TryRecvError::Empty) => {},
+            }
+
+        }
+
+        self.pending.is_some()
+
+    }
+
+    // Takes the waiting character, if there is one, without waiting
+    pub fn poll(&mut self) -> Option<u8>{
+
+        if self.status() { self.pending.take() } else { None }
+
+    }
+
+    pub fn ended(&self) -> bool{
+
+        self.ended && self.pending.is_none()
+
+    }
+
+    // Waits for a character, None once the input has ended
+    pub fn read(&mut self) -> Option<u8>{
+
+        self.flush();
+
+        self.pending.take().or_else(|| self.input.recv().ok())
+
+    }
+
+    pub fn write(&mut self, byte: u8){
+
+        self.output.write_all(&[byte]).expect("Failed to write to console");
+
+    }
+
+    // Writes a character that was typed, unless the terminal already showed it
+    pub fn echo(&mut self, bytes: &[u8]){
+
+        if self.echo{
+
+            self.output.write_all(bytes).expect("Failed to write to console");
+
+        }
+
+    }
+
+    pub fn flush(&mut self){
+
+        self.output.flush().expect("Failed to flush console");
+
+    }
+
+}
+
+// Sends what a client types until it disconnects, false once the console has been dropped
+fn receive(stream: TcpStream, sender: &Sender<u8>) -> bool{
+
+    let mut telnet = Telnet::default();
+
+    for byte in io::BufReader::new(stream).bytes().map_while(Result::ok){
+
+        if let Some(byte) = telnet.filter(byte){
+
+            if sender.send(byte).is_err(){
+
+                return false;
+
+            }
+
+        }
+
+    }
+
+    true
+
+}
+
+struct Connection{
+    client: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Write for Connection{
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize>{
+
+        let mut client = self.client.lock().expect("Failed to lock serial connection");
+
+        if client.as_mut().is_some_and(|stream| stream.write_all(bytes).is_err()){
+
+            *client = None; // Gone, its thread waits for the next client
+
+        }
+
+        Ok(bytes.len())
+
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+
+        Ok(())
+
+    }
+
+}
+
+#[derive(Default, PartialEq)]
+enum TelnetState{
+    #[default]
+    Data,
+    CarriageReturn, // A line feed or null after it is part of the same line ending
+    Command, // After IAC
+    Option, // After WILL, WONT, DO or DONT
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+// Removes the telnet commands clients send, which are all refused by never being answered,
+// and turns line endings into the carriage return programs expect
+#[derive(Default)]
+struct Telnet{
+    state: TelnetState,
+}
+
+impl Telnet{
+
+    const IAC: u8 = 0xFF;
+    const SB: u8 = 0xFA;
+    const SE: u8 = 0xF0;
+
+    fn filter(&mut self, byte: u8) -> Option<u8>{
+
+        let state = std::mem::take(&mut self.state);
+
+        match (state, byte) {
+
+            (TelnetState::Data, Telnet::IAC) | (TelnetState::CarriageReturn, Telnet::IAC) => self.state = TelnetState::Command,
+
+            (TelnetState::CarriageReturn, b'\n') | (TelnetState::CarriageReturn, 0) => {},
+
+            (TelnetState::Data, b'\r') | (TelnetState::CarriageReturn, b'\r') | (TelnetState::Data, b'\n') => {
+
+                self.state = TelnetState::CarriageReturn;
+
+                return Some(b'\r');
+
+            },
+
+            (TelnetState::Data, _) | (TelnetState::CarriageReturn, _) | (TelnetState::Command, Telnet::IAC) => return Some(byte), // IAC IAC is a data byte
+
+            (TelnetState::Command, Telnet::SB) => self.state = TelnetState::Subnegotiation,
+
+            (TelnetState::Command, 0xFB..=0xFE) => self.state = TelnetState::Option,
+
+            (TelnetState::Command, _) | (TelnetState::Option, _) => {},
+
+            (TelnetState::Subnegotiation, Telnet::IAC) => self.state = TelnetState::SubnegotiationCommand,
+
+            (TelnetState::SubnegotiationCommand, Telnet::SE) => {},
+
+            (TelnetState::Subnegotiation, _) | (TelnetState::SubnegotiationCommand, _) => self.state = TelnetState::Subnegotiation,
+
+        }
+
+        None
+
+    }
+
+}
+
+// Collects everything written to a console so tests can check it
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Capture(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Capture{
+
+    // An echoing console whose input is sent through the sender and whose output is captured
+    pub fn console() -> (Console, Sender<u8>, Capture){
+
+        let (sender, input) = mpsc::channel();
+
+        let capture = Capture::default();
+
+        (Console::new(input, Box::new(capture.clone()), true), sender, capture)
+
+    }
+
+    pub fn text(&self) -> String{
+
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+
+    }
+
+}
+
+#[cfg(test)]
+impl Write for Capture{
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize>{
+
+        self.0.borrow_mut().extend_from_slice(bytes);
+
+        Ok(bytes.len())
+
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+
+    #[test]
+    fn serial_console_over_tcp(){
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+
+        let address = listener.local_addr().expect("Failed to find the listening address");
+
+        let mut console = Console::listen(listener);
+
+        let mut client = TcpStream::connect(address).expect("Failed to connect");
+
+        client.write_all(b"\xFF\xFD\x01hi\r\n\xFF\xFA\x18\x00VT100\xFF\xF0\xFF\xFF\r\0").expect("Failed to send"); // With DO ECHO and a terminal type
+
+        let received: Vec<u8> = (0..5).map(|_| console.read().expect("Input ended")).collect();
+
+        assert_eq!(received, b"hi\r\xFF\r");
+
+        console.write(b'!');
+
+        let mut reply = [0; 1];
+
+        client.read_exact(&mut reply).expect("Failed to receive");
+
+        assert_eq!(&reply, b"!");
+
+        drop(client);
+
+        console.write(b'?'); // Dropped, there's no client
+
+    }
+
+}
@@ -0,0 +1,214 @@
+// File control blocks, the 36 byte structures programs pass to the BDOS to name a file and track their position in it
+// Layout: drive, name (8), type (3), extent, S1, S2, record count, allocation (16), current record, random record (3)
+// The top bit of each name and type byte is an attribute, e.g. read only, and is not part of the name
+
+pub const SIZE: usize = 36;
+pub const RECORD_SIZE: usize = 128;
+
+const NAME: std::ops::Range<usize> = 1..12; // Name and type
+const EXTENT: usize = 12;
+const S2: usize = 14; // Counts extents in units of 32
+const RECORD_COUNT: usize = 15;
+const RENAME: std::ops::Range<usize> = 17..28; // The new name given to rename
+const CURRENT_RECORD: usize = 32;
+const RANDOM_RECORD: usize = 33;
+
+const RECORDS_PER_EXTENT: u32 = 128;
+const EXTENTS_PER_S2: u32 = 32;
+
+// A name padded with spaces, 8 characters followed by 3, which may contain ? to match any character
+pub type Name = [u8; 11];
+
+pub fn name(fcb: &[u8]) -> Name{
+
+    let mut name = [b' '; 11];
+
+    for (index, byte) in fcb[NAME].iter().enumerate(){
+
+        name[index] = (byte & 0x7F).to_ascii_uppercase();
+
+    }
+
+    name
+
+}
+
+pub fn set_name(fcb: &mut [u8], name: &Name){
+
+    fcb[NAME].copy_from_slice(name);
+
+}
+
+pub fn new_name(fcb: &[u8]) -> Name{
+
+    let mut name = [b' '; 11];
+
+    for (index, byte) in fcb[RENAME].iter().enumerate(){
+
+        name[index] = (byte & 0x7F).to_ascii_uppercase();
+
+    }
+
+    name
+
+}
+
+pub fn matches(pattern: &Name, name: &Name) -> bool{
+
+    pattern.iter().zip(name.iter()).all(|(pattern, character)| *pattern == b'?' || pattern == character)
+
+}
+
+// The host file name, e.g. NAME.TYP, or NAME if the type is blank
+pub fn to_host(name: &Name) -> String{
+
+    let base = String::from_utf8_lossy(&name[..8]).trim_end().to_string();
+
+    let extension = String::from_utf8_lossy(&name[8..]).trim_end().to_string();
+
+    if extension.is_empty() { base } else { format!("{}.{}", base, extension) }
+
+}
+
+// Only host files with names CP/M could hold are visible, i.e. up to 8 characters and a type of up to 3
+pub fn from_host(file_name: &str) -> Option<Name>{
+
+    let (base, extension) = file_name.split_once('.').unwrap_or((file_name, ""));
+
+    let valid = |text: &str, length: usize| text.len() <= length
+        && text.bytes().all(|byte| byte.is_ascii_graphic() && !b".,;:=?*[]<>|".contains(&byte));
+
+    if base.is_empty() || !valid(base, 8) || !valid(extension, 3){
+
+        return None;
+
+    }
+
+    let mut name = [b' '; 11];
+
+    name[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+
+    name[8..8 + extension.len()].copy_from_slice(extension.to_ascii_uppercase().as_bytes());
+
+    Some(name)
+
+}
+
+// Fills the drive, name and type from a command line argument such as B:*.TXT, as the CCP does for 005CH and 006CH
+pub fn parse(argument: &str, fcb: &mut [u8]){
+
+    let argument = argument.to_ascii_uppercase();
+
+    let (drive, file_name) = match argument.as_bytes() {
+        [letter @ b'A'..=b'P', b':', ..] => (letter - b'A' + 1, &argument[2..]),
+        _ => (0, argument.as_str()),
+    };
+
+    fcb[0] = drive;
+
+    let (base, extension) = file_name.split_once('.').unwrap_or((file_name, ""));
+
+    for (field, text, length) in [(1, base, 8), (9, extension, 3)]{
+
+        let mut bytes = text.as_bytes().to_vec();
+
+        if let Some(index) = text.find('*'){ // Matches the rest of the field
+
+            bytes.truncate(index);
+
+            bytes.resize(length, b'?');
+
+        }
+
+        bytes.resize(length, b' ');
+
+        fcb[field..field + length].copy_from_slice(&bytes[..length]);
+
+    }
+
+}
+
+// Record number of the next sequential read or write, from the extent, S2 and current record
+pub fn sequential_record(fcb: &[u8]) -> u32{
+
+    ((fcb[S2] as u32 & 0x3F) * EXTENTS_PER_S2 + (fcb[EXTENT] as u32 & 0x1F)) * RECORDS_PER_EXTENT + (fcb[CURRENT_RECORD] as u32 & 0x7F)
+
+}
+
+pub fn set_sequential_record(fcb: &mut [u8], record: u32){
+
+    let extent = record / RECORDS_PER_EXTENT;
+
+    fcb[CURRENT_RECORD] = (record % RECORDS_PER_EXTENT) as u8;
+
+    fcb[EXTENT] = (extent % EXTENTS_PER_S2) as u8;
+
+    fcb[S2] = (extent / EXTENTS_PER_S2) as u8;
+
+}
+
+// Sets the number of records in the current extent from the size of the file
+pub fn set_record_count(fcb: &mut [u8], file_records: u32){
+
+    let extent_start = sequential_record(fcb) / RECORDS_PER_EXTENT * RECORDS_PER_EXTENT;
+
+    fcb[RECORD_COUNT] = file_records.saturating_sub(extent_start).min(RECORDS_PER_EXTENT) as u8;
+
+}
+
+pub fn random_record(fcb: &[u8]) -> u32{
+
+    u32::from_le_bytes([fcb[RANDOM_RECORD], fcb[RANDOM_RECORD + 1], fcb[RANDOM_RECORD + 2], 0])
+
+}
+
+pub fn set_random_record(fcb: &mut [u8], record: u32){
+
+    fcb[RANDOM_RECORD..RANDOM_RECORD + 3].copy_from_slice(&record.to_le_bytes()[..3]);
+
+}
+
+// The 32 byte directory entry written to the DMA buffer by search first and next
+pub fn directory_entry(name: &Name, file_records: u32) -> [u8; 32]{
+
+    let mut entry = [0; 32];
+
+    entry[NAME].copy_from_slice(name);
+
+    entry[RECORD_COUNT] = file_records.min(RECORDS_PER_EXTENT) as u8;
+
+    entry
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+
+    #[test]
+    fn names_and_records(){
+
+        let mut fcb = [0; SIZE];
+
+        parse("b:*.txt", &mut fcb);
+
+        assert_eq!(fcb[0], 2);
+
+        assert_eq!(&name(&fcb), b"????????TXT");
+
+        assert!(matches(&name(&fcb), &from_host("notes.txt").expect("Valid name")));
+
+        assert_eq!(from_host("toolongname.txt"), None);
+
+        assert_eq!(to_host(b"MBASIC  COM"), "MBASIC.COM");
+
+        set_sequential_record(&mut fcb, 32 * 128 + 130);
+
+        assert_eq!((fcb[S2], fcb[EXTENT], fcb[CURRENT_RECORD]), (1, 1, 2));
+
+        assert_eq!(sequential_record(&fcb), 32 * 128 + 130);
+
+    }
+
+}
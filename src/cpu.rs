@@ -1,15 +1,136 @@
-mod disassembler;
+pub mod call_stack;
+pub mod coverage;
+pub mod disassembler;
+pub mod history;
+pub mod io_map;
+pub mod opcodes;
+pub mod profiler;
+pub mod trace;
+pub mod watchpoint;
 
 use crate::machine::AudioController;
+use crate::intel_hex::{self, Segment};
+use call_stack::CallStack;
+use coverage::Coverage;
+use history::History;
+use io_map::{Acknowledge, IoMap};
+use profiler::Profiler;
+use trace::Trace;
+use watchpoint::{AccessKind, WatchAction, Watchpoint};
 
 use std::mem;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
+use std::ops::Range;
 
 pub struct FileToLoad{
     pub name: String,
-    pub offset: usize,
-    pub size: usize,
+    pub offset: usize, // Added to the addresses in Intel HEX files
+    pub size: usize, // 0 reads the whole file, ignored for Intel HEX files
+    pub read_only: bool, // ROM, which the program can't write to
+}
+
+impl FileToLoad{
+
+    // Parses <file>[@<address>], where the address is hexadecimal and defaults to 0
+    pub fn parse(text: &str, read_only: bool) -> Result<Self, String>{
+
+        let (name, offset) = match text.rsplit_once('@') {
+            Some((name, address)) => (name, parse_address(address)? as usize),
+            None => (text, 0),
+        };
+
+        Ok(FileToLoad{
+            name: name.to_string(),
+            offset,
+            size: 0,
+            read_only,
+        })
+
+    }
+
+    // Files ending in .hex are read as Intel HEX, anything else as a raw binary placed at the offset
+    pub fn read(&self) -> Result<Vec<Segment>, String>{
+
+        if self.name.to_lowercase().ends_with(".hex"){
+
+            let mut segments = intel_hex::load(&self.name)?;
+
+            for segment in &mut segments{
+
+                let address = segment.address as usize + self.offset;
+
+                if address + segment.bytes.len() > 0x10000{
+
+                    return Err(format!("'{}' does not fit in the 64KiB address space at offset {:04x}", self.name, self.offset));
+
+                }
+
+                segment.address = address as u16;
+
+            }
+
+            return Ok(segments);
+
+        }
+
+        let mut bytes = std::fs::read(&self.name).map_err(|_| format!("Unable to read '{}'", self.name))?;
+
+        if self.size != 0{
+
+            if bytes.len() < self.size{
+
+                return Err(format!("'{}' is smaller than the expected {} bytes", self.name, self.size));
+
+            }
+
+            bytes.truncate(self.size);
+
+        }
+
+        if self.offset + bytes.len() > 0x10000{
+
+            return Err(format!("'{}' does not fit in the 64KiB address space at offset {:04x}", self.name, self.offset));
+
+        }
+
+        Ok(vec![Segment{
+            address: self.offset as u16,
+            bytes,
+        }])
+
+    }
+
+    // Reads the file into memory, growing memory as required
+    // Returns the addresses of each segment loaded, which are apart in Intel HEX files with gaps between their records
+    pub fn load_into(&self, memory: &mut Vec<u8>) -> Vec<Range<usize>>{
+
+        let segments = self.read().expect("Failed to load file");
+
+        let mut ranges = vec![];
+
+        for segment in segments{
+
+            let segment_start = segment.address as usize;
+
+            let segment_end = segment_start + segment.bytes.len();
+
+            if memory.len() < segment_end{
+
+                memory.resize(segment_end, 0);
+
+            }
+
+            memory[segment_start..segment_end].copy_from_slice(&segment.bytes);
+
+            ranges.push(segment_start..segment_end);
+
+        }
+
+        ranges
+
+    }
+
 }
 
 pub struct Processor8080{
@@ -26,17 +147,19 @@ pub struct Processor8080{
     stack_pointer: u16,
     program_counter: u16,
     pub cycles_elapsed: u16,
+    pub total_cycles: u64, // Cycles since the machine started, never reset
 
     pub memory: Vec<u8>,
 
     flags: Flags,
 
     interrupt_enabled: bool,
+    interrupt_pending_enable: bool, // Set by EI, which only enables interrupts once the next instruction has run
     pub interrupt_value: u8,
+    halted: bool, // Set by HLT until an interrupt is taken or the CPU is reset
 
-    rom_size: u16,
-
-    opcode_cycle_length: [u16; 256],
+    pub read_only: Vec<Range<usize>>, // Writes to these addresses are ignored, e.g. ROM or addresses with no memory fitted
+    pub mirror_ram: bool, // Writes to 4000H-5FFFH go to 2000H-3FFFH, as the RAM of Space Invaders is mirrored there
 
     input_handler: fn(&mut Self, u8, &Vec<u8>) -> u8,
     output_handler: fn(&mut Self, u8, u8, &mut Vec<u8>, &mut AudioController),
@@ -44,9 +167,43 @@ pub struct Processor8080{
     pub testing: bool,
     pub debug: bool,
 
+    pub watchpoints: Vec<Watchpoint>,
+    instruction_address: u16, // Address of the instruction currently being executed
+    break_reason: Option<String>,
+    resume_address: Option<u16>, // Skip execute watchpoints once when resuming at this address
+
+    pub formatter: disassembler::Formatter, // Used for debug output, holds any loaded symbols
+
+    pub trace: Option<Trace>,
+
+    pub history: History,
+    history_reported: bool, // The history is only dumped automatically once per run
+
+    pub profiler: Option<Profiler>,
+
+    pub coverage: Option<Coverage>,
+
+    pub call_stack: CallStack,
+
+    pub io: IoMap, // Devices attached to ports, checked before the input and output handlers
+
     pub logger: std::boxed::Box<dyn std::io::Write>,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct Registers{
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub flags: u8, // Format: S Z 0 AC 0 P 1 C
+    pub stack_pointer: u16,
+    pub program_counter: u16,
+}
+
 #[derive(Default, Debug)]
 struct Flags{
     zero: bool,
@@ -56,6 +213,21 @@ struct Flags{
     auxiliary_carry: bool,
 }
 
+// Dumps the history if a panic unwinds past the processor
+impl Drop for Processor8080{
+
+    fn drop(&mut self){
+
+        if std::thread::panicking(){
+
+            let _ = self.report_history("Panic"); // Errors are ignored as a second panic would abort
+
+        }
+
+    }
+
+}
+
 impl Processor8080{
 
     pub fn new(
@@ -84,49 +256,47 @@ impl Processor8080{
             custom_registers: vec![],
             stack_pointer: 0, program_counter: 0,
             cycles_elapsed: 0,
+            total_cycles: 0,
             memory: vec![],
             flags: Default::default(),
             interrupt_enabled: false,
+            interrupt_pending_enable: false,
+            halted: false,
             interrupt_value: 1,
-            rom_size: 0,
+            read_only: vec![],
+            mirror_ram: false,
             input_handler: input_handler,
             output_handler: output_handler,
             testing: false,
             debug: false,
+            watchpoints: vec![],
+            instruction_address: 0,
+            break_reason: None,
+            resume_address: None,
+            formatter: Default::default(),
+            trace: None,
+            history: History::new(history::DEFAULT_HISTORY_SIZE),
+            history_reported: false,
+            profiler: None,
+            coverage: None,
+            call_stack: CallStack::default(),
+            io: IoMap::default(),
             logger: logger,
-
-            opcode_cycle_length: 
-                [
-                //  0   1   2   3   4   5   6   7   8   9   A   B   C   D   E   F <- Lowest order bits
-                    4,  10, 7,  5,  5,  5,  7,  4,  4,  10, 7,  5,  5,  5,  7,  4,  // 0
-                    4,  10, 7,  5,  5,  5,  7,  4,  4,  10, 7,  5,  5,  5,  7,  4,  // 1
-                    4,  10, 16, 5,  5,  5,  7,  4,  4,  10, 16, 5,  5,  5,  7,  4,  // 2
-                    4,  10, 13, 5,  10, 10, 10, 4,  4,  10, 13, 5,  5,  5,  7,  4,  // 3
-                    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,  // 4
-                    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,  // 5
-                    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,  // 6
-                    7,  7,  7,  7,  7,  7,  7,  7,  5,  5,  5,  5,  5,  5,  7,  5,  // 7
-                    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // 8
-                    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // 9
-                    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // A
-                    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // B
-                    5,  10, 10, 10, 11, 11, 7,  11, 5,  10, 10, 10, 11, 11, 7,  11, // C
-                    5,  10, 10, 10, 11, 11, 7,  11, 5,  10, 10, 10, 11, 11, 7,  11, // D
-                    5,  10, 10, 18, 11, 11, 7,  11, 5,  5,  10, 5,  11, 11, 7,  11, // E
-                    5,  10, 10, 4,  11, 11, 7,  11, 5,  5,  10, 4,  11, 11, 7,  11  // F
-                ],
         }
         
     }
 
     pub fn test(&mut self){
 
-        self.load_file("cpudiag.bin".to_string(), 0x100, 1453);
+        self.load_file(FileToLoad{
+            name: "cpudiag.bin".to_string(),
+            offset: 0x100,
+            size: 1453,
+            read_only: false,
+        });
 
         // Handle outputs - return instantly
         self.memory[0x06] = 0xC9;
-
-        self.rom_size = 0;
     
         while self.memory.len() < 0x10000{ // Initialize 64KiB of memory
 
@@ -142,41 +312,110 @@ impl Processor8080{
 
     }
 
-    pub fn initialize(&mut self, files: Vec<FileToLoad>){
+    pub fn initialize(&mut self, files: Vec<FileToLoad>, memory_size: usize){
 
         for file in files{
             
-            self.load_file(file.name, file.offset, file.size);
+            self.load_file(file);
 
         }
     
-        while self.memory.len() < 0x4000{ // Initialize 16KiB of memory
+        while self.memory.len() < memory_size{
             
             self.memory.push(0);
 
         }
+
+        self.io.reset(&mut self.memory);
         
     }
 
-    fn load_file(&mut self, file_name: String, offset: usize, buffer_size: usize){
+    fn load_file(&mut self, file: FileToLoad){
 
-        while self.memory.len() < offset{
+        let segments = file.load_into(&mut self.memory);
 
-            self.memory.push(0);
+        if file.read_only{
+
+            self.read_only.extend(segments.iter().cloned()); // Gaps between segments stay writable
 
         }
 
-        use std::io::Read;
+        if let Some(coverage) = &mut self.coverage{
+
+            let start = segments.iter().map(|segment| segment.start).min().unwrap_or(file.offset);
+
+            let end = segments.iter().map(|segment| segment.end).max().unwrap_or(file.offset);
+
+            coverage.regions.push(coverage::Region{
+                name: file.name,
+                addresses: start..end, // From the first byte loaded to the one after the last
+            });
+
+        }
+
+    }
+
+    pub fn is_read_only(&self, address: u16) -> bool{
+
+        self.read_only.iter().any(|addresses| addresses.contains(&(address as usize)))
+
+    }
+
+    // Control of the CPU as a front panel has it, so machines with switches don't change its state directly
+
+    // As the RESET line does, the other registers are left as they are
+    pub fn reset(&mut self){
+
+        self.program_counter = 0;
+
+        self.interrupt_enabled = false;
+
+        self.interrupt_pending_enable = false;
+
+        self.halted = false;
+
+    }
+
+    // Leaves a halt without an interrupt, as the RUN switch of the Altair does
+    pub fn resume(&mut self){
+
+        self.halted = false;
+
+    }
+
+    pub fn halted(&self) -> bool{
+
+        self.halted
 
-        let mut file = File::open(file_name).expect("File not found");
+    }
+
+    // Continues execution from the address
+    pub fn jump(&mut self, address: u16){
 
-        let mut buffer = vec![0u8; buffer_size];
+        self.program_counter = address;
 
-        self.rom_size += buffer_size as u16;
+    }
 
-        file.read_exact(&mut buffer).expect("Failed to read file");
+    pub fn examine(&self, address: u16) -> u8{
 
-        self.memory.append(&mut buffer);
+        self.memory.get(address as usize).copied().unwrap_or(0)
+
+    }
+
+    // Writes to memory as the program would, except that watchpoints aren't checked
+    pub fn deposit(&mut self, address: u16, value: u8){
+
+        if !self.is_read_only(address) && (address as usize) < self.memory.len(){
+
+            self.memory[address as usize] = value;
+
+        }
+
+    }
+
+    pub fn interrupts_enabled(&self) -> bool{
+
+        self.interrupt_enabled
 
     }
 
@@ -187,22 +426,180 @@ impl Processor8080{
             return;
 
         }
+
+        self.interrupt((8 * self.interrupt_value) as u16, 11);
+
+    }
+
+    // Executes the RST or CALL instruction supplied while the interrupt is acknowledged
+    fn interrupt(&mut self, address: u16, cycles: u8){
     
-        push_address_onto_stack(self, self.program_counter);
+        let return_address = self.program_counter;
+
+        push_address_onto_stack(self, return_address);
     
-        self.program_counter = (8 * self.interrupt_value) as u16;
+        self.program_counter = address;
         
-        self.cycles_elapsed += 11;
+        self.cycles_elapsed += cycles as u16;
+
+        self.total_cycles += cycles as u64;
+
+        if let Some(profiler) = &mut self.profiler{
+
+            profiler.interrupt(self.program_counter, self.stack_pointer, cycles as u64);
+
+        }
+
+        self.call_stack.interrupt(self.program_counter, return_address, self.stack_pointer);
 
         self.interrupt_enabled = false;
+
+        self.interrupt_pending_enable = false;
+
+        self.halted = false;
     
     }
 
+    pub fn registers(&self) -> Registers{
+
+        let mut flags: u8 = 0b00000010;
+
+        if self.flags.sign{
+            flags |= 0b10000000;
+        }
+        if self.flags.zero{
+            flags |= 0b01000000;
+        }
+        if self.flags.auxiliary_carry{
+            flags |= 0b00010000;
+        }
+        if self.flags.parity{
+            flags |= 0b00000100;
+        }
+        if self.flags.carry{
+            flags |= 0b00000001;
+        }
+
+        Registers{
+            a: self.a, b: self.b, c: self.c, d: self.d, e: self.e, h: self.h, l: self.l,
+            flags,
+            stack_pointer: self.stack_pointer,
+            program_counter: self.program_counter,
+        }
+
+    }
+
+    // Sets every register from the values given, e.g. to return results from a routine emulated outside the CPU
+    pub fn set_registers(&mut self, registers: &Registers){
+
+        self.a = registers.a;
+        self.b = registers.b;
+        self.c = registers.c;
+        self.d = registers.d;
+        self.e = registers.e;
+        self.h = registers.h;
+        self.l = registers.l;
+
+        self.flags = Flags{
+            sign: registers.flags & 0b10000000 != 0,
+            zero: registers.flags & 0b01000000 != 0,
+            auxiliary_carry: registers.flags & 0b00010000 != 0,
+            parity: registers.flags & 0b00000100 != 0,
+            carry: registers.flags & 0b00000001 != 0,
+        };
+
+        self.stack_pointer = registers.stack_pointer;
+
+        self.program_counter = registers.program_counter;
+
+    }
+
+    pub fn request_break(&mut self, reason: String){
+
+        self.break_reason = Some(reason);
+
+    }
+
+    // Writes the instruction history to the logger when emulation goes wrong, e.g. on a panic or an invalid opcode
+    pub fn report_history(&mut self, reason: &str) -> io::Result<()>{
+
+        if self.history_reported || !self.history.is_enabled(){
+
+            return Ok(());
+
+        }
+
+        self.history_reported = true;
+
+        writeln!(self.logger, "\n{}, the last instructions executed were (oldest first):", reason)?;
+
+        self.history.write(usize::MAX, &self.formatter, &mut self.logger)?;
+
+        writeln!(self.logger, "Backtrace:")?;
+
+        self.call_stack.write(self.program_counter, &self.memory, &self.formatter, &mut self.logger)?;
+
+        self.logger.flush()
+
+    }
+
+    // Writes the profile and coverage map and flushes the logger and any trace, called before the emulator exits
+    pub fn flush_output(&mut self){
+
+        self.write_profile();
+
+        self.write_coverage();
+
+        self.logger.flush().expect("Failed to flush output buffer");
+
+        if let Some(trace) = &mut self.trace{
+
+            trace.flush().expect("Failed to flush trace file");
+
+        }
+
+    }
+
+    // Writes the profile report so far, also bound to a key so it can be taken while running
+    pub fn write_profile(&mut self){
+
+        if let Some(profiler) = &self.profiler{
+
+            profiler.write_to_file(&self.formatter).expect("Failed to write profile");
+
+        }
+
+    }
+
+    // Writes the coverage map and logs the summary for each loaded file
+    pub fn write_coverage(&mut self){
+
+        if let Some(coverage) = &self.coverage{
+
+            coverage.write_to_file().expect("Failed to write coverage map");
+
+            for line in coverage.summary(){
+
+                writeln!(self.logger, "Coverage of {}", line).expect("Failed to write to output buffer");
+
+            }
+
+        }
+
+    }
+
+    // Returns the reason emulation should pause, if a watchpoint or the user requested it
+    pub fn take_break(&mut self) -> Option<String>{
+
+        self.break_reason.take()
+
+    }
+
     pub fn debug_output(&mut self){
         
         write!(self.logger, "\n\n==============\n\n").expect("Failed to write to output buffer");
         
-        disassembler::check_opcode_8080(self.program_counter as usize, &self.memory, &mut self.logger);
+        disassembler::check_opcode_8080(self.program_counter as usize, &self.memory, &self.formatter, &mut self.logger);
     
         write!(self.logger, "Memory:\n\t0x{:x}\n\t0x{:x}\n\t0x{:x}\n", 
             self.memory[self.program_counter as usize],
@@ -260,7 +657,7 @@ impl Processor8080{
         }
         else if self.program_counter == 0{
 
-            self.logger.flush().expect("Failed to flush output buffer");
+            self.flush_output();
 
             audio_controller.close();
 
@@ -270,17 +667,102 @@ impl Processor8080{
 
     }
 
+    // Executes one instruction, then advances the devices by the cycles it took, so every caller that steps the CPU keeps them in time
     pub fn emulate(&mut self, ports: &mut Vec<u8>, audio_controller: &mut AudioController){
 
+        let start_cycles = self.total_cycles;
+
+        self.execute(ports, audio_controller);
+
+        self.io.tick((self.total_cycles - start_cycles) as u32);
+
+    }
+
+    fn execute(&mut self, ports: &mut Vec<u8>, audio_controller: &mut AudioController){
+
         if self.testing{
 
             self.check_cpudiag_status(audio_controller);
 
         }
+
+        if self.interrupt_enabled{
+
+            match self.io.acknowledge() { // A device interrupts before the next instruction is fetched
+                Some(Acknowledge::Restart(restart)) => self.interrupt(8 * restart as u16, 11),
+                Some(Acknowledge::Call(address)) => self.interrupt(address, 17),
+                None => {},
+            }
+
+        }
+
+        if self.halted{ // Idles with devices still ticked until an interrupt is taken
+
+            self.cycles_elapsed += 4;
+
+            self.total_cycles += 4;
+
+            return;
+
+        }
     
         let opcode: u8 = self.memory[self.program_counter as usize];
+
+        self.instruction_address = self.program_counter;
+
+        if !self.watchpoints.is_empty() && self.resume_address.take() != Some(self.program_counter){
+
+            check_watchpoints(self, AccessKind::Execute, self.program_counter, opcode);
+
+            if self.break_reason.is_some(){ // Break before the instruction is executed
+
+                self.resume_address = Some(self.program_counter);
+
+                return;
+
+            }
+
+        }
+
+        if self.history.is_enabled(){
+
+            let program_counter = self.program_counter as usize;
+
+            let byte = |offset: usize| self.memory.get(program_counter + offset).copied().unwrap_or(0);
+
+            let entry = history::Entry{
+                total_cycles: self.total_cycles,
+                registers: self.registers(),
+                bytes: [opcode, byte(1), byte(2)],
+            };
+
+            self.history.record(entry);
+
+        }
+
+        if let Some(coverage) = &mut self.coverage{
+
+            coverage.mark_instruction(self.program_counter, opcodes::OPCODE_TABLE[opcode as usize].length);
+
+        }
+
+        if self.trace.is_some(){
+
+            let registers = self.registers();
+
+            if let Some(trace) = &mut self.trace{
+
+                trace.record(&registers, &self.memory, self.total_cycles).expect("Failed to write to trace file");
+
+            }
+
+        }
         
-        self.cycles_elapsed += self.opcode_cycle_length[opcode as usize];
+        let start_cycles = self.total_cycles;
+
+        self.cycles_elapsed += opcodes::OPCODE_TABLE[opcode as usize].cycles as u16;
+
+        self.total_cycles += opcodes::OPCODE_TABLE[opcode as usize].cycles as u64;
 
         if opcode != 0x00 && self.debug{ // Don't display NOP instructions to avoid log clutter
 
@@ -289,6 +771,8 @@ impl Processor8080{
         }
     
         self.program_counter += 1;
+
+        let enabling_interrupts = self.interrupt_pending_enable; // EI was the previous instruction
     
         match opcode {
     
@@ -297,14 +781,24 @@ impl Processor8080{
             ********************************************/
             //#region
             0x00 => {}, // NOP
-            0xF3 => self.interrupt_enabled = false, // DI
-            0xFB => self.interrupt_enabled = true, // EI
+            0xF3 => {
+                self.interrupt_enabled = false;
+                self.interrupt_pending_enable = false;
+            }, // DI
+            0xFB => self.interrupt_pending_enable = true, // EI
             0xD3 => {
-                (self.output_handler)(self, self.memory[self.program_counter as usize], self.a, ports, audio_controller);
+                let port = self.memory[self.program_counter as usize];
+                if !self.io.output(port, self.a, &mut self.memory){
+                    (self.output_handler)(self, port, self.a, ports, audio_controller);
+                }
                 self.program_counter += 1;
             }, // OUT
             0xDB => {
-                self.a = (self.input_handler)(self, self.memory[self.program_counter as usize], ports);
+                let port = self.memory[self.program_counter as usize];
+                self.a = match self.io.input(port) {
+                    Some(value) => value,
+                    None => (self.input_handler)(self, port, ports),
+                };
                 self.program_counter += 1;
             }, // IN
             0x27 => {
@@ -320,7 +814,13 @@ impl Processor8080{
                 add(self, correction, false);
                 self.flags.carry = carry_temp;
             }, // DAA
-            0x76 => panic!("Halting"), // HLT
+            0x76 => { // HLT
+
+                self.report_history("HLT executed").expect("Failed to write to output buffer");
+
+                self.halted = true;
+
+            },
             //#endregion
                 
     
@@ -343,7 +843,10 @@ impl Processor8080{
             0x43 => self.b = self.e, // MOV B,E
             0x44 => self.b = self.h, // MOV B,H
             0x45 => self.b = self.l, // MOV B,L
-            0x46 => self.b = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV B,(HL)
+            0x46 => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.b = read_from_memory(self, address);
+            }, // MOV B,(HL)
             0x47 => self.b = self.a, // MOV B,A
     
             0x48 => self.c = self.b, // MOV C,B
@@ -352,7 +855,10 @@ impl Processor8080{
             0x4B => self.c = self.e, // MOV C,E
             0x4C => self.c = self.h, // MOV C,H
             0x4D => self.c = self.l, // MOV C,L
-            0x4E => self.c = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV D,(HL)
+            0x4E => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.c = read_from_memory(self, address);
+            }, // MOV D,(HL)
             0x4F => self.c = self.a, // MOV C,A
     
             0x50 => self.d = self.b, // MOV D,B
@@ -361,7 +867,10 @@ impl Processor8080{
             0x53 => self.d = self.e, // MOV D,E
             0x54 => self.d = self.h, // MOV D,H
             0x55 => self.d = self.l, // MOV D,L
-            0x56 => self.d = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV D(HL)
+            0x56 => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.d = read_from_memory(self, address);
+            }, // MOV D(HL)
             0x57 => self.d = self.a, // MOV D,A
     
             0x58 => self.e = self.b, // MOV E,B
@@ -370,7 +879,10 @@ impl Processor8080{
             0x5B => {}, // MOV E,E - Does nothing
             0x5C => self.e = self.h, // MOV E,H
             0x5D => self.e = self.l, // MOV E,L
-            0x5E => self.e = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV E,(HL)
+            0x5E => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.e = read_from_memory(self, address);
+            }, // MOV E,(HL)
             0x5F => self.e = self.a, // MOV E,A
             
             0x60 => self.h = self.b, // MOV H,B
@@ -379,7 +891,10 @@ impl Processor8080{
             0x63 => self.h = self.e, // MOV H,E
             0x64 => {}, // MOV H,H - Does nothing
             0x65 => self.h = self.l, // MOV H,L
-            0x66 => self.h = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV H,(HL)
+            0x66 => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.h = read_from_memory(self, address);
+            }, // MOV H,(HL)
             0x67 => self.h = self.a, // MOV H,A
             
             0x68 => self.l = self.b, // MOV L,B
@@ -388,7 +903,10 @@ impl Processor8080{
             0x6B => self.l = self.e, // MOV L,E
             0x6C => self.l = self.h, // MOV L,H
             0x6D => {}, // MOV L,L - Does nothing
-            0x6E => self.l = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV L,(HL)
+            0x6E => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.l = read_from_memory(self, address);
+            }, // MOV L,(HL)
             0x6F => self.l = self.a, // MOV L,A
             
             0x70 => write_to_memory(self, self.h, self.l, self.b), // MOV (HL),B
@@ -405,7 +923,10 @@ impl Processor8080{
             0x7B => self.a = self.e, // MOV A,E
             0x7C => self.a = self.h, // MOV A,H
             0x7D => self.a = self.l, // MOV A,L
-            0x7E => self.a = self.memory[get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize], // MOV A,(HL)
+            0x7E => {
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                self.a = read_from_memory(self, address);
+            }, // MOV A,(HL)
             0x7F => {}, // MOV A,A - Does nothing
     
             0x06 => {
@@ -468,24 +989,24 @@ impl Processor8080{
                 flags
             }), // PUSH PSW
             0xC1 => {
-                self.b = self.memory[(self.stack_pointer + 1) as usize];
-                self.c = self.memory[self.stack_pointer as usize];
+                self.b = read_from_memory(self, self.stack_pointer + 1);
+                self.c = read_from_memory(self, self.stack_pointer);
                 self.stack_pointer += 2;
             }, // POP B
             0xD1 => {
-                self.d = self.memory[(self.stack_pointer + 1) as usize];
-                self.e = self.memory[self.stack_pointer as usize];
+                self.d = read_from_memory(self, self.stack_pointer + 1);
+                self.e = read_from_memory(self, self.stack_pointer);
                 self.stack_pointer += 2;
             }, // POP D
             0xE1 => {
-                self.h = self.memory[(self.stack_pointer + 1) as usize];
-                self.l = self.memory[self.stack_pointer as usize];
+                self.h = read_from_memory(self, self.stack_pointer + 1);
+                self.l = read_from_memory(self, self.stack_pointer);
                 self.stack_pointer += 2;
             }, // POP H
             0xF1 => {
                 // Format: S Z 0 AC 0 P 1 C
-                self.a = self.memory[(self.stack_pointer + 1) as usize];
-                let flag_values = self.memory[self.stack_pointer as usize];
+                self.a = read_from_memory(self, self.stack_pointer + 1);
+                let flag_values = read_from_memory(self, self.stack_pointer);
                 self.flags.sign = flag_values & 0b10000000 != 0;
                 self.flags.zero = flag_values & 0b01000000 != 0;
                 self.flags.parity = flag_values & 0b00000100 != 0;
@@ -494,8 +1015,13 @@ impl Processor8080{
             }, // POP PSW
             0xF9 => self.stack_pointer = ((self.h as u16) << 8) | (self.l as u16), // SPHL
             0xE3 => {
-                mem::swap(&mut self.h, &mut self.memory[(self.stack_pointer + 1) as usize]);
-                mem::swap(&mut self.l, &mut self.memory[self.stack_pointer as usize]);
+                let high_address = self.stack_pointer.wrapping_add(1);
+                let stack_high = read_from_memory(self, high_address);
+                let stack_low = read_from_memory(self, self.stack_pointer);
+                write_to_memory(self, (high_address >> 8) as u8, high_address as u8, self.h);
+                write_to_memory(self, (self.stack_pointer >> 8) as u8, self.stack_pointer as u8, self.l);
+                self.h = stack_high;
+                self.l = stack_low;
             }, // XTHL
             //#endregion
 
@@ -556,19 +1082,25 @@ impl Processor8080{
                 let mut first_byte = self.memory[(self.program_counter + 1) as usize];
                 let mut second_byte = self.memory[self.program_counter as usize];
                 let address = get_address_from_pair(&mut first_byte, &mut second_byte, (self.memory.len() - 1) as u16);
-                self.a = self.memory[address as usize];
+                self.a = read_from_memory(self, address);
                 self.program_counter += 2;
             }, // LDA addr
             0x2A => {
                 let mut first_byte = self.memory[(self.program_counter + 1) as usize];
                 let mut second_byte = self.memory[self.program_counter as usize];
                 let address = get_address_from_pair(&mut first_byte, &mut second_byte, (self.memory.len() - 1) as u16);
-                self.h = self.memory[(address + 1) as usize];
-                self.l = self.memory[address as usize];
+                self.h = read_from_memory(self, address + 1);
+                self.l = read_from_memory(self, address);
                 self.program_counter += 2;
             }, // LHLD addr
-            0x0A => self.a = self.memory[get_address_from_pair(&mut self.b, &mut self.c, (self.memory.len() - 1) as u16) as usize], // LDAX B
-            0x1A => self.a = self.memory[get_address_from_pair(&mut self.d, &mut self.e, (self.memory.len() - 1) as u16) as usize], // LDAX D
+            0x0A => {
+                let address = get_address_from_pair(&mut self.b, &mut self.c, (self.memory.len() - 1) as u16);
+                self.a = read_from_memory(self, address);
+            }, // LDAX B
+            0x1A => {
+                let address = get_address_from_pair(&mut self.d, &mut self.e, (self.memory.len() - 1) as u16);
+                self.a = read_from_memory(self, address);
+            }, // LDAX D
             //#endregion
 
 
@@ -607,9 +1139,8 @@ impl Processor8080{
                 self.l = answer as u8;
             }, // INR L
             0x34 => {
-                let answer: u16 = (self.memory[
-                    get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize
-                ] as u16) + 1;
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                let answer: u16 = (read_from_memory(self, address) as u16) + 1;
                 step_register_flags(self, answer);
                 write_to_memory(self, self.h, self.l, answer as u8);
             }, // INR M
@@ -656,9 +1187,8 @@ impl Processor8080{
                 self.l = answer as u8;
             }, // DCR L
             0x35 => {
-                let answer: u16 = (self.memory[
-                    get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16) as usize
-                ] as u32 + get_twos_complement(1) as u32) as u16;
+                let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
+                let answer: u16 = (read_from_memory(self, address) as u32 + get_twos_complement(1) as u32) as u16;
                 step_register_flags(self, answer);
                 write_to_memory(self, self.h, self.l, answer as u8);
             }, // DCR M
@@ -734,7 +1264,8 @@ impl Processor8080{
             0x85 => add(self, self.l, false), // ADD L
             0x86 => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                add(self, self.memory[address as usize], false);
+                let value = read_from_memory(self, address);
+                add(self, value, false);
             }, // ADD M - From memory address
             0x87 => add(self, self.a, false), // ADD A
             0x88 => add(self, self.b, self.flags.carry), // ADC B
@@ -745,7 +1276,8 @@ impl Processor8080{
             0x8D => add(self, self.l, self.flags.carry), // ADC L
             0x8E => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                add(self, self.memory[address as usize], self.flags.carry);
+                let value = read_from_memory(self, address);
+                add(self, value, self.flags.carry);
             }, // ADC M - From memory address
             0x8F => add(self, self.a, self.flags.carry), // ADC A
             0xC6 => {
@@ -785,7 +1317,8 @@ impl Processor8080{
             0x95 => subtract(self, self.l, false), // SUB L
             0x96 => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                subtract(self, self.memory[address as usize], false);
+                let value = read_from_memory(self, address);
+                subtract(self, value, false);
             }, // SUB M - From memory address
             0x97 => subtract(self, self.a, false), // SUB A
             0x98 => subtract(self, self.b, self.flags.carry), // SBB B
@@ -796,7 +1329,8 @@ impl Processor8080{
             0x9D => subtract(self, self.l, self.flags.carry), // SBB L
             0x9E => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                subtract(self, self.memory[address as usize], self.flags.carry);
+                let value = read_from_memory(self, address);
+                subtract(self, value, self.flags.carry);
             }, // SBB M - From memory address
             0x9F => subtract(self, self.a, self.flags.carry), // SBB A
             0xD6 => {
@@ -851,7 +1385,8 @@ impl Processor8080{
             0xBD => compare(self, self.l), // CMP L
             0xBE => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                compare(self, self.memory[address as usize])
+                let value = read_from_memory(self, address);
+                compare(self, value)
             }, // CMP M
             0xBF => compare(self, self.a), // CMP A
             0xFE => {
@@ -873,7 +1408,8 @@ impl Processor8080{
             0xA5 => and(self, self.l), // ANA L
             0xA6 => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                and(self, self.memory[address as usize])
+                let value = read_from_memory(self, address);
+                and(self, value)
             }, // ANA M
             0xA7 => and(self, self.a), // ANA A
             0xE6 => {
@@ -895,7 +1431,8 @@ impl Processor8080{
             0xB5 => or(self, self.l), // ORA L
             0xB6 => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                or(self, self.memory[address as usize])
+                let value = read_from_memory(self, address);
+                or(self, value)
             }, // ORA M
             0xB7 => or(self, self.a),  // ORA A
             0xF6 => {
@@ -917,7 +1454,8 @@ impl Processor8080{
             0xAD => xor(self, self.l), // XRA L
             0xAE => {
                 let address = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16);
-                xor(self, self.memory[address as usize])
+                let value = read_from_memory(self, address);
+                xor(self, value)
             },  // XRA M
             0xAF => xor(self, self.a), // XRA A
             0xEE => {
@@ -998,11 +1536,25 @@ impl Processor8080{
             0xE9 => self.program_counter = get_address_from_pair(&mut self.h, &mut self.l, (self.memory.len() - 1) as u16), // PCHL
             //#endregion
     
-            _ => {},
+            _ => { // Undocumented opcodes are treated as NOP
+
+                let reason = format!("Invalid opcode 0x{:02x} at 0x{:04x}", opcode, self.instruction_address);
+
+                self.report_history(&reason).expect("Failed to write to output buffer");
+
+            },
     
         }
+
+        if enabling_interrupts && self.interrupt_pending_enable{ // Unless the instruction after EI was DI
+
+            self.interrupt_enabled = true;
+
+            self.interrupt_pending_enable = false;
+
+        }
     
-        if self.stack_pointer < self.rom_size || self.stack_pointer >= (self.memory.len() - 1) as u16{ // Ensuring ROM is not overwritten
+        if self.is_read_only(self.stack_pointer) || self.stack_pointer >= (self.memory.len() - 1) as u16{ // Ensuring ROM is not overwritten
             
             self.stack_pointer = 0;
     
@@ -1013,6 +1565,14 @@ impl Processor8080{
             self.program_counter = 0;
     
         }
+
+        if let Some(profiler) = &mut self.profiler{
+
+            profiler.record(self.instruction_address, opcode, self.program_counter, self.stack_pointer, self.total_cycles - start_cycles);
+
+        }
+
+        self.call_stack.update(self.instruction_address, opcode, self.program_counter, self.stack_pointer, &self.memory);
     
     }
 
@@ -1051,6 +1611,22 @@ fn get_address_from_pair(byte_1: &mut u8, byte_2: &mut u8, max_memory_index: u16
 
     address
 
+}
+
+// Parses a hexadecimal address, with or without a 0x prefix
+pub fn parse_address(text: &str) -> Result<u16, String>{
+
+    let text = text.trim();
+
+    u16::from_str_radix(hex_digits(text), 16).map_err(|_| format!("Invalid address '{}'", text))
+
+}
+
+// Drops a leading 0x or 0X
+pub fn hex_digits(text: &str) -> &str{
+
+    text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text)
+
 }
 //#endregion
 
@@ -1204,6 +1780,13 @@ fn push_address_onto_stack(processor: &mut Processor8080, address: u16){
 // byte_1 is highest order bits, byte_2 is lowest order bits
 fn push_onto_stack(processor: &mut Processor8080, byte_1: u8, byte_2: u8){
 
+    if !processor.watchpoints.is_empty(){
+
+        check_watchpoints(processor, AccessKind::Write, processor.stack_pointer.wrapping_sub(1), byte_1);
+        check_watchpoints(processor, AccessKind::Write, processor.stack_pointer.wrapping_sub(2), byte_2);
+
+    }
+
     processor.memory[((processor.stack_pointer as u32 + get_twos_complement(1) as u32) as u16) as usize] = byte_1;  // Push return address onto the stack
     processor.memory[((processor.stack_pointer as u32 + get_twos_complement(2) as u32) as u16) as usize] = byte_2;  // Highest order bits are pushed at SP - 1
                                                                                                                     // Lowerst order bits are pushed at SP - 2
@@ -1211,17 +1794,43 @@ fn push_onto_stack(processor: &mut Processor8080, byte_1: u8, byte_2: u8){
 
 }
 
+fn read_from_memory(processor: &mut Processor8080, address: u16) -> u8{
+
+    let value = processor.memory[address as usize];
+
+    if !processor.watchpoints.is_empty(){
+
+        check_watchpoints(processor, AccessKind::Read, address, value);
+
+    }
+
+    if let Some(coverage) = &mut processor.coverage{
+
+        coverage.mark_data(address);
+
+    }
+
+    value
+
+}
+
 fn write_to_memory(processor: &mut Processor8080, byte_1: u8, byte_2: u8, value: u8){
 
     let mut address: u16 = get_address_from_pair(&mut {byte_1}, &mut {byte_2}, (processor.memory.len() - 1) as u16);
 
-    if address < processor.rom_size{
+    if !processor.watchpoints.is_empty(){
+
+        check_watchpoints(processor, AccessKind::Write, address, value);
+
+    }
+
+    if processor.is_read_only(address){
 
         return;
 
     }
 
-    if address >= 0x4000 && address < 0x6000{
+    if processor.mirror_ram && (0x4000..0x6000).contains(&address){
 
         address -= 0x2000;
 
@@ -1229,6 +1838,32 @@ fn write_to_memory(processor: &mut Processor8080, byte_1: u8, byte_2: u8, value:
 
     processor.memory[address as usize] = value;
 
+}
+
+fn check_watchpoints(processor: &mut Processor8080, kind: AccessKind, address: u16, value: u8){
+
+    for index in 0..processor.watchpoints.len(){
+
+        if !processor.watchpoints[index].matches(kind, address, value){
+
+            continue;
+
+        }
+
+        let description = format!("Watchpoint {} ({}): {:?} 0x{:02x} at 0x{:04x}, PC: 0x{:04x}",
+            index, processor.watchpoints[index], kind, value, address, processor.instruction_address
+        );
+
+        match processor.watchpoints[index].action {
+
+            WatchAction::Log => writeln!(processor.logger, "{}", description).expect("Failed to write to output buffer"),
+
+            WatchAction::Break => processor.break_reason = Some(description),
+
+        }
+
+    }
+
 }
 //#endregion
 
@@ -1306,6 +1941,8 @@ fn call(processor: &mut Processor8080, flag: bool){
 
     if flag {
 
+        take_branch(processor);
+
         push_address_onto_stack(processor, processor.program_counter + 2);
 
         processor.program_counter = get_address_from_pair(
@@ -1327,9 +1964,11 @@ fn ret(processor: &mut Processor8080, flag: bool){
 
     if flag {
 
+        take_branch(processor);
+
         processor.program_counter = get_address_from_pair(
-            &mut {processor.memory[(processor.stack_pointer + 1) as usize]},
-            &mut {processor.memory[processor.stack_pointer as usize]},
+            &mut read_from_memory(processor, processor.stack_pointer + 1),
+            &mut read_from_memory(processor, processor.stack_pointer),
             (processor.memory.len() - 1) as u16,
         );
 
@@ -1339,11 +1978,85 @@ fn ret(processor: &mut Processor8080, flag: bool){
 
 }
 
+// Conditional calls and returns take longer when the condition is met
+fn take_branch(processor: &mut Processor8080){
+
+    let info = &opcodes::OPCODE_TABLE[processor.memory[processor.instruction_address as usize] as usize];
+
+    if let Some(branch_cycles) = info.branch_cycles{
+
+        let extra_cycles = branch_cycles - info.cycles;
+
+        processor.cycles_elapsed += extra_cycles as u16;
+
+        processor.total_cycles += extra_cycles as u64;
+
+    }
+
+}
+
 fn reset(processor: &mut Processor8080, address: u16){
 
-    push_address_onto_stack(processor, processor.program_counter + 2);
+    push_address_onto_stack(processor, processor.program_counter);
 
     processor.program_counter = address;
 
 }
-//#endregion
\ No newline at end of file
+//#endregion
+
+// Runs small programs against devices, for the tests of devices and machines
+#[cfg(test)]
+pub mod testing{
+
+    use super::*;
+    use crate::asm::assemble;
+    use io_map::Device;
+
+    use std::ops::RangeInclusive;
+
+    pub type Devices = Vec<(RangeInclusive<u8>, Box<dyn Device>)>;
+
+    // Attaches the devices to their ports, resets them with 64KiB of memory and assembles the source into it
+    pub fn load_program(source: &str, devices: Devices) -> Processor8080{
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        for (ports, device) in devices{
+
+            processor.io.attach(ports, device);
+
+        }
+
+        processor.initialize(vec![], 0x10000);
+
+        let (origin, program) = assemble(source).expect("Failed to assemble").to_binary();
+
+        processor.memory[origin as usize..origin as usize + program.len()].copy_from_slice(&program);
+
+        processor
+
+    }
+
+    pub fn run(processor: &mut Processor8080, instructions: usize){
+
+        for _ in 0..instructions{
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+            processor.cycles_elapsed = 0; // Only counted per frame by the machines, and would overflow here
+
+        }
+
+    }
+
+    pub fn run_program(source: &str, devices: Devices, instructions: usize) -> Processor8080{
+
+        let mut processor = load_program(source, devices);
+
+        run(&mut processor, instructions);
+
+        processor
+
+    }
+
+}
@@ -0,0 +1,264 @@
+// A shadow of the calls the program has made, as the 8080 keeps nothing but return addresses on its stack
+// Frames are pushed by CALL, RST and interrupts and popped once the stack pointer moves above their return address
+use super::disassembler::Formatter;
+use super::opcodes::{Flow, OPCODE_TABLE};
+
+use std::io::{self, Write};
+
+const MAX_DEPTH: usize = 256; // The oldest frames are dropped beyond this, e.g. when a program never returns from its calls
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame{
+    pub routine: u16, // Address that was called
+    pub call_address: u16, // Address of the call, or of the instruction that was interrupted
+    pub return_address: u16,
+    pub stack_pointer: u16, // Where the return address is stored
+    pub interrupt: bool,
+}
+
+#[derive(Default)]
+pub struct CallStack{
+    frames: Vec<Frame>, // Innermost last
+}
+
+impl CallStack{
+
+    // Called after each instruction with the program counter and stack pointer it left behind
+    pub fn update(&mut self, address: u16, opcode: u8, program_counter: u16, stack_pointer: u16, memory: &[u8]){
+
+        let info = &OPCODE_TABLE[opcode as usize];
+
+        let next_address = address.wrapping_add(info.length as u16);
+
+        match (info.flow, opcode) {
+
+            (Flow::Call | Flow::ConditionalCall | Flow::Restart, _) if program_counter != next_address => {
+
+                self.push(Frame{
+                    routine: program_counter,
+                    call_address: address,
+                    return_address: next_address,
+                    stack_pointer,
+                    interrupt: false,
+                });
+
+            },
+
+            (Flow::Return | Flow::ConditionalReturn, _) if program_counter != next_address => self.unwind(stack_pointer),
+
+            (_, 0x31 | 0x33 | 0x3B | 0xF9) => self.unwind(stack_pointer), // LXI SP, INX SP, DCX SP and SPHL
+
+            (_, 0xE3) => { // XTHL swaps the return address of a frame at the top of the stack with HL
+
+                if let Some(frame) = self.frames.last_mut().filter(|frame| frame.stack_pointer == stack_pointer){
+
+                    frame.return_address = read_word(memory, stack_pointer).unwrap_or(frame.return_address);
+
+                }
+
+            },
+
+            _ => {},
+
+        }
+
+    }
+
+    // Called after the return address has been pushed and the program counter set to the vector
+    pub fn interrupt(&mut self, vector: u16, return_address: u16, stack_pointer: u16){
+
+        self.push(Frame{
+            routine: vector,
+            call_address: return_address,
+            return_address,
+            stack_pointer,
+            interrupt: true,
+        });
+
+    }
+
+    fn push(&mut self, frame: Frame){
+
+        self.unwind(frame.stack_pointer.wrapping_add(1)); // Frames at or below the new return address were abandoned
+
+        if self.frames.len() == MAX_DEPTH{
+
+            self.frames.remove(0);
+
+        }
+
+        self.frames.push(frame);
+
+    }
+
+    // Drops every frame whose return address is below the stack pointer
+    fn unwind(&mut self, stack_pointer: u16){
+
+        while self.frames.last().is_some_and(|frame| frame.stack_pointer < stack_pointer){
+
+            self.frames.pop();
+
+        }
+
+    }
+
+    // Writes a backtrace, innermost first, starting with the current program counter and then the call of each frame
+    pub fn write(&self, program_counter: u16, memory: &[u8], formatter: &Formatter, writer: &mut dyn Write) -> io::Result<()>{
+
+        let location = |address: u16| formatter.symbols.describe(address).map_or(String::new(), |location| format!("  {}", location));
+
+        writeln!(writer, "#0  {:04X}{}", program_counter, location(program_counter))?;
+
+        for (index, frame) in self.frames.iter().rev().enumerate(){
+
+            let mut notes = vec![];
+
+            if frame.interrupt{
+
+                notes.push("interrupted".to_string());
+
+            }
+
+            match read_word(memory, frame.stack_pointer) {
+
+                Some(address) if address == frame.return_address => {},
+
+                Some(address) => notes.push(format!("return address {:04X} overwritten with {:04X}", frame.return_address, address)),
+
+                None => notes.push("return address outside memory".to_string()),
+
+            }
+
+            let notes = if notes.is_empty() { String::new() } else { format!("  ({})", notes.join(", ")) };
+
+            writeln!(writer, "#{}  {:04X}{}{}", index + 1, frame.call_address, location(frame.call_address), notes)?;
+
+        }
+
+        Ok(())
+
+    }
+
+}
+
+fn read_word(memory: &[u8], address: u16) -> Option<u16>{
+
+    let low = *memory.get(address as usize)?;
+
+    let high = *memory.get(address as usize + 1)?;
+
+    Some(((high as u16) << 8) | low as u16)
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+    use crate::cpu::Processor8080;
+    use crate::cpu::disassembler::symbols::SymbolTable;
+    use crate::machine::AudioController;
+
+    #[test]
+    fn follows_calls_and_stack_changes(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H    ; 0000
+                    CALL OUTER      ; 0003
+                    HLT             ; 0006
+            OUTER:  CALL INNER      ; 0007
+                    RET             ; 000A
+            INNER:  LXI H,0006H     ; 000B
+                    XTHL            ; 000E
+                    NOP             ; 000F
+                    RET             ; 0010
+        ";
+
+        let (_, rom) = assemble(source).expect("Failed to assemble").to_binary();
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        processor.memory = rom;
+        processor.memory.resize(0x200, 0);
+
+        for _ in 0..5{ // Up to the NOP in INNER, after XTHL changed where it returns to
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        let routines: Vec<(u16, u16)> = processor.call_stack.frames.iter().map(|frame| (frame.routine, frame.return_address)).collect();
+
+        assert_eq!(routines, vec![(0x0007, 0x0006), (0x000B, 0x0006)]);
+
+        let symbols = SymbolTable::parse("0007 OUTER\n000B INNER\n").expect("Failed to parse symbols");
+
+        let formatter = Formatter{ symbols, ..Default::default() };
+
+        let mut output = vec![];
+
+        processor.call_stack.write(0x000F, &processor.memory, &formatter, &mut output).expect("Failed to write backtrace");
+
+        assert_eq!(String::from_utf8(output).expect("Backtrace is not UTF-8"), "#0  000F  INNER+0x4\n#1  0007  OUTER\n#2  0003\n");
+
+        for _ in 0..2{ // NOP and RET, which returns straight to 0006 with the return address of OUTER still on the stack
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        let routines: Vec<u16> = processor.call_stack.frames.iter().map(|frame| frame.routine).collect();
+
+        assert_eq!(routines, vec![0x0007]);
+
+    }
+
+    #[test]
+    fn returns_from_restarts(){
+
+        let source = "
+                    ORG 0
+                    JMP START       ; 0000
+                    ORG 0008H
+                    INR A           ; 0008
+                    RET             ; 0009
+            START:  LXI SP,0100H    ; 000A
+                    RST 1           ; 000D
+                    MVI B,42H       ; 000E
+                    HLT             ; 0010
+        ";
+
+        let (_, rom) = assemble(source).expect("Failed to assemble").to_binary();
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        processor.memory = rom;
+        processor.memory.resize(0x200, 0);
+
+        for _ in 0..4{ // Up to the RET of RST 1
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        let mut output = vec![];
+
+        processor.call_stack.write(0x0009, &processor.memory, &Formatter::default(), &mut output).expect("Failed to write backtrace");
+
+        assert_eq!(String::from_utf8(output).expect("Backtrace is not UTF-8"), "#0  0009\n#1  000D\n");
+
+        for _ in 0..2{ // RET and MVI B,42H
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        assert_eq!((processor.registers().program_counter, processor.registers().b), (0x0010, 0x42));
+
+        assert!(processor.call_stack.frames.is_empty());
+
+    }
+
+}
@@ -0,0 +1,218 @@
+// Records how every byte of memory was used during a session, so code can be told apart from data
+// Map file format, one line per 16 bytes that were accessed:
+// <address> <16 flag digits>, each digit a sum of the flags below or . if never accessed, e.g. 0100 1221.12.4444....
+// Lines starting with ; are comments, the summary of each loaded file is written as comments
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+
+pub const OPCODE: u8 = 1; // Executed as the first byte of an instruction
+pub const OPERAND: u8 = 2; // Read as part of an instruction
+pub const DATA: u8 = 4; // Read by an instruction, e.g. MOV A,M or LHLD
+
+const BYTES_PER_LINE: usize = 16;
+
+// The addresses filled by a loaded file, reported separately in the summary
+pub struct Region{
+    pub name: String,
+    pub addresses: Range<usize>,
+}
+
+pub struct Coverage{
+    file_name: String,
+    flags: Vec<u8>, // Indexed by address
+    pub regions: Vec<Region>,
+}
+
+impl Coverage{
+
+    pub fn new(file_name: &str) -> Coverage{
+
+        Coverage{
+            file_name: file_name.to_string(),
+            flags: vec![0; 0x10000],
+            regions: vec![],
+        }
+
+    }
+
+    pub fn load(file_name: &str) -> Result<Coverage, String>{
+
+        let text = std::fs::read_to_string(file_name).map_err(|_| format!("Unable to read '{}'", file_name))?;
+
+        let mut coverage = Coverage::new(file_name);
+
+        coverage.parse(&text).map_err(|error| format!("{}: {}", file_name, error))?;
+
+        Ok(coverage)
+
+    }
+
+    fn parse(&mut self, text: &str) -> Result<(), String>{
+
+        for (line_index, line) in text.lines().enumerate(){
+
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(';'){
+
+                continue;
+
+            }
+
+            let error = || format!("line {}: expected <address> <flags>", line_index + 1);
+
+            let (address, flags) = line.split_once(' ').ok_or_else(error)?;
+
+            let address = u16::from_str_radix(address, 16).map_err(|_| error())? as usize;
+
+            for (offset, flag) in flags.trim().chars().enumerate(){
+
+                let value = match flag {
+                    '.' => 0,
+                    _ => flag.to_digit(16).filter(|value| *value <= (OPCODE | OPERAND | DATA) as u32).ok_or_else(error)? as u8,
+                };
+
+                *self.flags.get_mut(address + offset).ok_or_else(error)? = value;
+
+            }
+
+        }
+
+        Ok(())
+
+    }
+
+    pub fn flags(&self, address: u16) -> u8{
+
+        self.flags[address as usize]
+
+    }
+
+    pub fn mark_instruction(&mut self, address: u16, length: u8){
+
+        self.flags[address as usize] |= OPCODE;
+
+        for offset in 1..length as u16{
+
+            self.flags[address.wrapping_add(offset) as usize] |= OPERAND;
+
+        }
+
+    }
+
+    pub fn mark_data(&mut self, address: u16){
+
+        self.flags[address as usize] |= DATA;
+
+    }
+
+    // One line per region: the share of its bytes accessed in any way, executed and read as data
+    pub fn summary(&self) -> Vec<String>{
+
+        self.regions.iter().map(|region| {
+
+            let flags = &self.flags[region.addresses.clone()];
+
+            let share = |mask: u8| match flags.len() {
+                0 => 0.0,
+                length => flags.iter().filter(|flag| **flag & mask != 0).count() as f64 * 100.0 / length as f64,
+            };
+
+            format!("{} {:04X}-{:04X}: {:.1}% accessed, {:.1}% executed, {:.1}% read as data",
+                region.name, region.addresses.start, region.addresses.end.saturating_sub(1),
+                share(OPCODE | OPERAND | DATA), share(OPCODE | OPERAND), share(DATA)
+            )
+
+        }).collect()
+
+    }
+
+    pub fn write_to_file(&self) -> io::Result<()>{
+
+        let mut writer = BufWriter::new(File::create(&self.file_name)?);
+
+        self.write(&mut writer)?;
+
+        writer.flush()
+
+    }
+
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<()>{
+
+        writeln!(writer, "; Coverage map: {} executed as an opcode, {} read as an operand, {} read as data, . never accessed", OPCODE, OPERAND, DATA)?;
+
+        for line in self.summary(){
+
+            writeln!(writer, "; {}", line)?;
+
+        }
+
+        for (index, flags) in self.flags.chunks(BYTES_PER_LINE).enumerate(){
+
+            if flags.iter().all(|flag| *flag == 0){
+
+                continue;
+
+            }
+
+            let digits: String = flags.iter().map(|flag| match flag {
+                0 => '.',
+                _ => std::char::from_digit(*flag as u32, 16).expect("Flags are a single digit"),
+            }).collect();
+
+            writeln!(writer, "{:04X} {}", index * BYTES_PER_LINE, digits)?;
+
+        }
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::Processor8080;
+    use crate::machine::AudioController;
+
+    #[test]
+    fn records_and_reads_back(){
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        // 0000: LDA 0008H; JMP 0000H
+        processor.memory = vec![0x3A, 0x08, 0x00, 0xC3, 0x00, 0x00, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00];
+        processor.coverage = Some(Coverage::new("unused"));
+
+        for _ in 0..2{
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        let mut coverage = processor.coverage.take().expect("Coverage was set");
+
+        coverage.regions.push(Region{ name: "ROM".to_string(), addresses: 0..10 });
+
+        assert_eq!(coverage.summary(), vec!["ROM 0000-0009: 70.0% accessed, 60.0% executed, 10.0% read as data".to_string()]);
+
+        let mut output = vec![];
+
+        coverage.write(&mut output).expect("Failed to write coverage");
+
+        let text = String::from_utf8(output).expect("Coverage is not UTF-8");
+
+        assert!(text.ends_with("\n0000 122122..4.......\n"));
+
+        let mut loaded = Coverage::new("unused");
+
+        loaded.parse(&text).expect("Failed to parse coverage");
+
+        assert_eq!(loaded.flags, coverage.flags);
+
+    }
+
+}
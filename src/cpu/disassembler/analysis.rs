@@ -0,0 +1,389 @@
+use super::decode;
+use super::listing::Line;
+use super::symbols::{SymbolKind, SymbolTable};
+use crate::cpu::coverage::{self, Coverage};
+use crate::cpu::parse_address;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ByteKind{
+    Data,
+    Opcode,
+    Operand,
+}
+
+// A table of 16 bit code addresses, typically indexed before a PCHL
+#[derive(Clone, Copy, Debug)]
+pub struct JumpTable{
+    pub address: u16,
+    pub count: u16,
+}
+
+impl JumpTable{
+
+    // Format: <address>:<number of entries>, e.g. 0a2e:4
+    pub fn parse(specification: &str) -> Result<JumpTable, String>{
+
+        let mut sections = specification.split(':');
+
+        let address = parse_address(sections.next().unwrap_or(""))?;
+
+        let count = sections.next()
+            .and_then(|count| count.trim().parse::<u16>().ok())
+            .ok_or_else(|| format!("Invalid jump table '{}', expected <address>:<number of entries>", specification))?;
+
+        Ok(JumpTable{
+            address,
+            count,
+        })
+
+    }
+
+    fn contains(&self, address: u16) -> bool{
+
+        address >= self.address && (address as u32) < self.address as u32 + self.count as u32 * 2
+
+    }
+
+}
+
+// A run of bytes listed with a given type instead of being traced, from a jump table or a data symbol
+enum Region{
+    Words(u16), // Number of words
+    Bytes(u16),
+    Text(u16),
+}
+
+fn region_at(address: u16, jump_tables: &[JumpTable], symbols: &SymbolTable) -> Option<Region>{
+
+    if let Some(table) = jump_tables.iter().find(|table| table.address == address){
+
+        return Some(Region::Words(table.count));
+
+    }
+
+    match symbols.get(address) {
+        Some(symbol) if symbol.kind == SymbolKind::Word => Some(Region::Words(symbol.count)),
+        Some(symbol) if symbol.kind == SymbolKind::Byte => Some(Region::Bytes(symbol.count)),
+        Some(symbol) if symbol.kind == SymbolKind::Text => Some(Region::Text(symbol.count)),
+        _ => None,
+    }
+
+}
+
+fn in_region(address: u16, jump_tables: &[JumpTable], symbols: &SymbolTable) -> bool{
+
+    jump_tables.iter().any(|table| table.contains(address))
+        || symbols.iter().any(|(start, symbol)| match symbol.data_length() {
+            Some(length) => address >= start && (address as u32) < start as u32 + length as u32,
+            None => false,
+        })
+
+}
+
+// Classifies every byte of a ROM as an opcode, an operand or data
+pub struct CodeMap{
+    pub origin: u16,
+    pub kinds: Vec<ByteKind>,
+}
+
+impl CodeMap{
+
+    pub fn new(origin: u16, size: usize) -> CodeMap{
+
+        CodeMap{
+            origin,
+            kinds: vec![ByteKind::Data; size],
+        }
+
+    }
+
+    fn offset(&self, address: u16) -> Option<usize>{
+
+        let offset = address.wrapping_sub(self.origin) as usize;
+
+        if offset < self.kinds.len(){
+
+            Some(offset)
+
+        }
+        else{
+
+            None
+
+        }
+
+    }
+
+    // Marks an instruction as code, returning false if it leaves the ROM or overlaps code already found
+    pub fn mark_instruction(&mut self, address: u16, length: u8) -> bool{
+
+        let offset = match self.offset(address) {
+
+            Some(offset) if offset + length as usize <= self.kinds.len() => offset,
+
+            _ => return false,
+
+        };
+
+        if self.kinds[offset..offset + length as usize].iter().any(|kind| *kind != ByteKind::Data){
+
+            return false;
+
+        }
+
+        self.kinds[offset] = ByteKind::Opcode;
+
+        for kind in &mut self.kinds[offset + 1..offset + length as usize]{
+
+            *kind = ByteKind::Operand;
+
+        }
+
+        true
+
+    }
+
+}
+
+// Follows every path of execution from the entry points, code symbols, jump table entries and instructions
+// a coverage map shows were executed, marking the bytes reached as code
+pub fn trace(rom: &[u8], origin: u16, entry_points: &[u16], jump_tables: &[JumpTable], symbols: &SymbolTable, coverage: Option<&Coverage>) -> CodeMap{
+
+    let mut code_map = CodeMap::new(origin, rom.len());
+
+    let mut pending: Vec<u16> = entry_points.to_vec();
+
+    pending.extend(symbols.iter().filter(|(_, symbol)| symbol.kind == SymbolKind::Code).map(|(address, _)| address));
+
+    for table in jump_tables{
+
+        for entry in 0..table.count{
+
+            let address = table.address.wrapping_add(entry * 2);
+
+            if let (Some(low), Some(high)) = (code_map.offset(address), code_map.offset(address.wrapping_add(1))){
+
+                pending.push(((rom[high] as u16) << 8) | rom[low] as u16);
+
+            }
+
+        }
+
+    }
+
+    // Traced first so the boundaries of instructions that really executed take precedence over guesses
+    // Bytes read as data are still traced into, as ROMs read their own code, e.g. as a source of random numbers
+    if let Some(coverage) = coverage{
+
+        pending.extend((0..rom.len()).map(|offset| origin.wrapping_add(offset as u16)).filter(|address| coverage.flags(*address) & coverage::OPCODE != 0));
+
+    }
+
+    while let Some(mut address) = pending.pop(){
+
+        while let Some(offset) = code_map.offset(address){
+
+            let instruction = decode(&rom[offset..], address).expect("Failed to decode an instruction"); // The offset is inside the ROM
+
+            if !instruction.is_defined() || in_region(address, jump_tables, symbols){
+
+                break; // Undocumented opcodes are assumed to be data that was reached by a wrong guess
+
+            }
+
+            if !code_map.mark_instruction(address, instruction.length){
+
+                break; // Already traced, or the instruction runs into existing code
+
+            }
+
+            if let Some(target) = instruction.target(){
+
+                pending.push(target);
+
+            }
+
+            if !instruction.falls_through(){
+
+                break;
+
+            }
+
+            address = address.wrapping_add(instruction.length as u16);
+
+        }
+
+    }
+
+    code_map
+
+}
+
+// Builds listing lines from a code map, with jump tables and data symbols emitted using their type and all other non-code bytes as data
+pub fn lines_from_code_map(rom: &[u8], code_map: &CodeMap, jump_tables: &[JumpTable], symbols: &SymbolTable) -> Vec<Line>{
+
+    let mut lines = vec![];
+
+    let mut offset = 0;
+
+    while offset < rom.len(){
+
+        let address = code_map.origin.wrapping_add(offset as u16);
+
+        if code_map.kinds[offset] == ByteKind::Opcode{
+
+            let instruction = decode(&rom[offset..], address).expect("Failed to decode an instruction"); // The offset is inside the ROM
+
+            offset += instruction.length as usize;
+
+            lines.push(Line::Code(instruction));
+
+            continue;
+
+        }
+
+        // Regions are cut short by the end of the ROM or by code that was traced into them
+        let data_end = (offset..rom.len()).find(|index| code_map.kinds[*index] == ByteKind::Opcode).unwrap_or(rom.len());
+
+        match region_at(address, jump_tables, symbols) {
+
+            Some(Region::Words(count)) if (data_end - offset) / 2 > 0 => {
+
+                let count = (count as usize).min((data_end - offset) / 2);
+
+                let words = rom[offset..offset + count * 2]
+                    .chunks(2)
+                    .map(|pair| ((pair[1] as u16) << 8) | pair[0] as u16)
+                    .collect();
+
+                offset += count * 2;
+
+                lines.push(Line::Words(address, words));
+
+            },
+
+            Some(Region::Bytes(length)) if length > 0 => {
+
+                let end = (offset + length as usize).min(data_end);
+
+                lines.push(Line::Data(address, rom[offset..end].to_vec()));
+
+                offset = end;
+
+            },
+
+            Some(Region::Text(length)) if length > 0 => {
+
+                let end = (offset + length as usize).min(data_end);
+
+                lines.push(Line::Text(address, rom[offset..end].to_vec()));
+
+                offset = end;
+
+            },
+
+            _ => {
+
+                let start = offset;
+
+                offset += 1;
+
+                while offset < data_end && region_at(code_map.origin.wrapping_add(offset as u16), jump_tables, symbols).is_none(){
+
+                    offset += 1;
+
+                }
+
+                lines.push(Line::Data(address, rom[start..offset].to_vec()));
+
+            },
+
+        }
+
+    }
+
+    lines
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+
+    const SOURCE: &str = "
+                ORG 0
+                LXI H,TABLE     ; 0000
+                JMP START       ; 0003
+        TABLE:  DW ONE,TWO      ; 0006, only indexed at runtime
+        START:  MVI A,1         ; 000A
+                CZ SUB          ; 000C
+                HLT             ; 000F
+                JMP START       ; 0010
+        SUB:    RET             ; 0013
+        ONE:    NOP             ; 0014
+        TWO:    RET             ; 0015
+                DB 'HI'         ; 0016
+    ";
+
+    fn opcodes(code_map: &CodeMap) -> Vec<u16>{
+
+        (0..code_map.kinds.len()).filter(|offset| code_map.kinds[*offset] == ByteKind::Opcode).map(|offset| offset as u16).collect()
+
+    }
+
+    // Lines as text, with the address of each
+    fn describe(lines: &[Line]) -> Vec<String>{
+
+        lines.iter().map(|line| match line {
+            Line::Code(instruction) => format!("{:04X} {}", instruction.address, instruction.mnemonic),
+            Line::Data(address, bytes) => format!("{:04X} DB {:02X?}", address, bytes),
+            Line::Words(address, words) => format!("{:04X} DW {:04X?}", address, words),
+            Line::Text(address, bytes) => format!("{:04X} TEXT {:02X?}", address, bytes),
+        }).collect()
+
+    }
+
+    #[test]
+    fn separates_code_from_data(){
+
+        let (origin, rom) = assemble(SOURCE).expect("Failed to assemble").to_binary();
+
+        let code_map = trace(&rom, origin, &[0x0000], &[], &SymbolTable::default(), None);
+
+        assert_eq!(opcodes(&code_map), vec![0x0000, 0x0003, 0x000A, 0x000C, 0x000F, 0x0010, 0x0013]);
+
+        let lines = lines_from_code_map(&rom, &code_map, &[], &SymbolTable::default());
+
+        assert_eq!(describe(&lines), vec![
+            "0000 LXI", "0003 JMP",
+            "0006 DB [14, 00, 15, 00]", // The jump table is never reached, so it and the routines it points to are data
+            "000A MVI", "000C CZ", "000F HLT", "0010 JMP", "0013 RET",
+            "0014 DB [00, C9, 48, 49]",
+        ]);
+
+    }
+
+    #[test]
+    fn follows_jump_tables(){
+
+        let (origin, rom) = assemble(SOURCE).expect("Failed to assemble").to_binary();
+
+        let jump_tables = [JumpTable::parse("0006:2").expect("Failed to parse jump table")];
+
+        let code_map = trace(&rom, origin, &[0x0000], &jump_tables, &SymbolTable::default(), None);
+
+        assert_eq!(opcodes(&code_map), vec![0x0000, 0x0003, 0x000A, 0x000C, 0x000F, 0x0010, 0x0013, 0x0014, 0x0015]);
+
+        let lines = lines_from_code_map(&rom, &code_map, &jump_tables, &SymbolTable::default());
+
+        assert_eq!(describe(&lines)[2], "0006 DW [0014, 0015]");
+
+        assert_eq!(describe(&lines)[8..], ["0014 NOP", "0015 RET", "0016 DB [48, 49]"]);
+
+        assert!(JumpTable::parse("0006").is_err());
+
+    }
+
+}
@@ -0,0 +1,387 @@
+use super::listing::Line;
+use super::{Flow, Formatter, Instruction};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EdgeKind{
+    Fallthrough, // Including the return point after a call
+    Jump,
+    Call,
+    Return, // From a block ending in a return to the return point of each call to its routine
+}
+
+// A run of instructions that is only entered at the start and only left at the end
+pub struct Block{
+    pub start: u16,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Block{
+
+    fn last(&self) -> &Instruction{
+
+        self.instructions.last().expect("Blocks always have an instruction")
+
+    }
+
+    // Address of the instruction after the block
+    fn next_address(&self) -> u16{
+
+        let last = self.last();
+
+        last.address.wrapping_add(last.length as u16)
+
+    }
+
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Edge{
+    pub from: u16,
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+pub struct Graph{
+    pub blocks: BTreeMap<u16, Block>,
+    pub edges: Vec<Edge>,
+}
+
+impl Graph{
+
+    // Splits the code lines of a listing into basic blocks, which start at an entry point, a branch target,
+    // a jump table entry, after any instruction that transfers control, or after data
+    pub fn build(lines: &[Line], entry_points: &[u16]) -> Graph{
+
+        let mut leaders: BTreeSet<u16> = entry_points.iter().copied().collect();
+
+        let mut previous_ends_block = true;
+
+        for line in lines{
+
+            match line {
+
+                Line::Code(instruction) => {
+
+                    if previous_ends_block{
+
+                        leaders.insert(instruction.address);
+
+                    }
+
+                    leaders.extend(instruction.target());
+
+                    previous_ends_block = instruction.flow != Flow::Sequential;
+
+                },
+
+                Line::Words(_, words) => {
+
+                    leaders.extend(words.iter().copied());
+
+                    previous_ends_block = true;
+
+                },
+
+                Line::Data(..) | Line::Text(..) => previous_ends_block = true,
+
+            }
+
+        }
+
+        let mut blocks = BTreeMap::new();
+
+        let mut current: Option<Block> = None;
+
+        for line in lines{
+
+            let instruction = match line {
+
+                Line::Code(instruction) => instruction,
+
+                _ => {
+
+                    if let Some(block) = current.take(){
+
+                        blocks.insert(block.start, block);
+
+                    }
+
+                    continue;
+
+                },
+
+            };
+
+            if leaders.contains(&instruction.address){
+
+                if let Some(block) = current.take(){
+
+                    blocks.insert(block.start, block);
+
+                }
+
+            }
+
+            let block = current.get_or_insert_with(|| Block{
+                start: instruction.address,
+                instructions: vec![],
+            });
+
+            block.instructions.push(instruction.clone());
+
+            if instruction.flow != Flow::Sequential{
+
+                let block = current.take().expect("Block was just inserted");
+
+                blocks.insert(block.start, block);
+
+            }
+
+        }
+
+        if let Some(block) = current.take(){
+
+            blocks.insert(block.start, block);
+
+        }
+
+        let mut graph = Graph{
+            blocks,
+            edges: vec![],
+        };
+
+        graph.add_edges();
+
+        graph
+
+    }
+
+    fn add_edges(&mut self){
+
+        let mut edges = vec![];
+
+        for block in self.blocks.values(){
+
+            let last = block.last();
+
+            let next = block.next_address();
+
+            if let Some(target) = last.target(){
+
+                let kind = match last.flow {
+                    Flow::Call | Flow::ConditionalCall | Flow::Restart => EdgeKind::Call,
+                    _ => EdgeKind::Jump,
+                };
+
+                edges.push(Edge{ from: block.start, to: target, kind });
+
+            }
+
+            if last.falls_through() && self.blocks.contains_key(&next){
+
+                edges.push(Edge{ from: block.start, to: next, kind: EdgeKind::Fallthrough });
+
+            }
+
+        }
+
+        // Each routine returns to the instruction after every call to it
+        let calls: Vec<Edge> = edges.iter().filter(|edge| edge.kind == EdgeKind::Call).copied().collect();
+
+        for call in calls{
+
+            let return_point = self.blocks[&call.from].next_address();
+
+            if !self.blocks.contains_key(&return_point){
+
+                continue;
+
+            }
+
+            for address in self.reachable(call.to, &edges){
+
+                if matches!(self.blocks[&address].last().flow, Flow::Return | Flow::ConditionalReturn){
+
+                    edges.push(Edge{ from: address, to: return_point, kind: EdgeKind::Return });
+
+                }
+
+            }
+
+        }
+
+        self.edges = edges;
+
+    }
+
+    // Blocks of the routine starting at the entry, found by following jumps and fallthroughs but not calls
+    fn reachable(&self, entry: u16, edges: &[Edge]) -> BTreeSet<u16>{
+
+        let mut found = BTreeSet::new();
+
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop(){
+
+            if !self.blocks.contains_key(&address) || !found.insert(address){
+
+                continue;
+
+            }
+
+            for edge in edges.iter().filter(|edge| edge.from == address){
+
+                match edge.kind {
+
+                    // The return point after a call is assumed to be reached, as most routines return
+                    EdgeKind::Jump | EdgeKind::Fallthrough => pending.push(edge.to),
+
+                    EdgeKind::Call | EdgeKind::Return => {},
+
+                }
+
+            }
+
+        }
+
+        found
+
+    }
+
+    // The blocks of a single routine, keeping the calls it makes but not the returns to its callers
+    pub fn routine(&self, entry: u16) -> Graph{
+
+        let addresses = self.reachable(entry, &self.edges);
+
+        Graph{
+            blocks: addresses.iter().map(|address| {
+                let block = &self.blocks[address];
+                (*address, Block{ start: block.start, instructions: block.instructions.clone() })
+            }).collect(),
+            edges: self.edges.iter()
+                .filter(|edge| addresses.contains(&edge.from) && edge.kind != EdgeKind::Return)
+                .copied()
+                .collect(),
+        }
+
+    }
+
+    // Edges to blocks outside the graph, such as the routines called, are drawn to an ellipse with the target's name
+    pub fn write_dot(&self, name: &str, formatter: &Formatter, writer: &mut dyn Write) -> io::Result<()>{
+
+        writeln!(writer, "digraph \"{}\" {{", escape(name))?;
+
+        writeln!(writer, "    node [shape=box, fontname=\"Courier\"];")?;
+
+        for block in self.blocks.values(){
+
+            let mut label = String::new();
+
+            if let Some(name) = formatter.label(block.start){
+
+                label.push_str(&format!("{}:\\l", escape(name)));
+
+            }
+
+            for instruction in &block.instructions{
+
+                label.push_str(&format!("{:04X}  {}\\l", instruction.address, escape(&formatter.format(instruction))));
+
+            }
+
+            writeln!(writer, "    b{:04X} [label=\"{}\"];", block.start, label)?;
+
+        }
+
+        let external: BTreeSet<u16> = self.edges.iter()
+            .map(|edge| edge.to)
+            .filter(|address| !self.blocks.contains_key(address))
+            .collect();
+
+        for address in external{
+
+            let name = match formatter.label(address) {
+                Some(name) => name.to_string(),
+                None => format!("{:04X}", address),
+            };
+
+            writeln!(writer, "    b{:04X} [label=\"{}\", shape=ellipse];", address, escape(&name))?;
+
+        }
+
+        for edge in &self.edges{
+
+            let style = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::Jump => " [color=blue]",
+                EdgeKind::Call => " [style=dashed, color=darkgreen]",
+                EdgeKind::Return => " [style=dotted, color=red]",
+            };
+
+            writeln!(writer, "    b{:04X} -> b{:04X}{};", edge.from, edge.to, style)?;
+
+        }
+
+        writeln!(writer, "}}")
+
+    }
+
+}
+
+fn escape(text: &str) -> String{
+
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+    use crate::cpu::disassembler::listing::linear_sweep;
+
+    #[test]
+    fn blocks_and_edges(){
+
+        let source = "
+                    ORG 0
+            START:  CALL SUB        ; 0000
+                    JZ START        ; 0003
+                    HLT             ; 0006
+            SUB:    DCR A           ; 0007
+                    RZ              ; 0008
+                    JMP SUB         ; 0009
+        ";
+
+        let (origin, rom) = assemble(source).expect("Failed to assemble").to_binary();
+
+        let graph = Graph::build(&linear_sweep(&rom, origin), &[0]);
+
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<u16>>(), vec![0x0000, 0x0003, 0x0006, 0x0007, 0x0009]);
+
+        let edge = |from, to, kind| Edge{ from, to, kind };
+
+        assert_eq!(graph.edges, vec![
+            edge(0x0000, 0x0007, EdgeKind::Call),
+            edge(0x0000, 0x0003, EdgeKind::Fallthrough),
+            edge(0x0003, 0x0000, EdgeKind::Jump),
+            edge(0x0003, 0x0006, EdgeKind::Fallthrough),
+            edge(0x0006, 0x0007, EdgeKind::Fallthrough),
+            edge(0x0007, 0x0009, EdgeKind::Fallthrough),
+            edge(0x0009, 0x0007, EdgeKind::Jump),
+            edge(0x0007, 0x0003, EdgeKind::Return),
+        ]);
+
+        let routine = graph.routine(0x0007);
+
+        assert_eq!(routine.blocks.keys().copied().collect::<Vec<u16>>(), vec![0x0007, 0x0009]);
+
+        assert!(routine.edges.iter().all(|edge| edge.kind != EdgeKind::Return));
+
+    }
+
+}
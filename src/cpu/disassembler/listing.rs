@@ -0,0 +1,360 @@
+use super::{decode, format_hex, Formatter, Instruction};
+use crate::cpu::opcodes::OPCODE_TABLE;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+
+pub enum Line{
+    Code(Instruction),
+    Data(u16, Vec<u8>), // Address and the bytes to emit as DB directives
+    Words(u16, Vec<u16>), // Address and the words to emit as DW directives
+    Text(u16, Vec<u8>), // Address and the characters to emit as DB directives
+}
+
+impl Line{
+
+    pub fn address(&self) -> u16{
+
+        match self {
+            Line::Code(instruction) => instruction.address,
+            Line::Data(address, _) | Line::Words(address, _) | Line::Text(address, _) => *address,
+        }
+
+    }
+
+    // Whether a label at the address can be placed on this line, splitting data lines where needed
+    fn can_label(&self, address: u16) -> bool{
+
+        let start = self.address();
+
+        let offset = address.wrapping_sub(start) as usize;
+
+        match self {
+            Line::Code(_) => address == start,
+            Line::Data(_, bytes) | Line::Text(_, bytes) => offset < bytes.len(),
+            Line::Words(_, words) => offset < words.len() * 2 && offset.is_multiple_of(2),
+        }
+
+    }
+
+}
+
+// Decodes every byte of the ROM as code in order, bytes of an instruction cut off by the end of the ROM become data
+pub fn linear_sweep(rom: &[u8], origin: u16) -> Vec<Line>{
+
+    let mut lines = vec![];
+
+    let mut offset = 0;
+
+    while offset < rom.len(){
+
+        let address = origin.wrapping_add(offset as u16);
+
+        if offset + OPCODE_TABLE[rom[offset] as usize].length as usize > rom.len(){
+
+            lines.push(Line::Data(address, rom[offset..].to_vec()));
+
+            break;
+
+        }
+
+        let instruction = decode(&rom[offset..], address).expect("Failed to decode an instruction"); // The whole instruction is inside the ROM
+
+        offset += instruction.length as usize;
+
+        lines.push(Line::Code(instruction));
+
+    }
+
+    lines
+
+}
+
+// Names every jump, call, restart and jump table target that starts an instruction in the listing
+pub fn generate_labels(lines: &[Line]) -> HashMap<u16, String>{
+
+    let instruction_addresses: BTreeSet<u16> = lines.iter()
+        .filter_map(|line| match line {
+            Line::Code(instruction) => Some(instruction.address),
+            _ => None,
+        })
+        .collect();
+
+    lines.iter()
+        .flat_map(|line| match line {
+            Line::Code(instruction) => instruction.target().into_iter().collect(),
+            Line::Words(_, words) => words.clone(),
+            Line::Data(..) | Line::Text(..) => vec![],
+        })
+        .filter(|target| instruction_addresses.contains(target))
+        .map(|target| (target, format!("L{:04X}", target)))
+        .collect()
+
+}
+
+// Writes a listing that can be reassembled, with the address and raw bytes of each line in a comment
+// Symbols that do not start a line, such as variables in RAM, are defined with EQU
+pub fn write_listing(rom: &[u8], origin: u16, lines: &[Line], formatter: &Formatter, writer: &mut dyn Write) -> io::Result<()>{
+
+    let mut has_equates = false;
+
+    for (address, symbol) in formatter.symbols.iter(){
+
+        if lines.iter().any(|line| line.can_label(address)){
+
+            continue;
+
+        }
+
+        let definition = format!("{} EQU {}", symbol.name, format_hex(address, 4));
+
+        match &symbol.comment {
+            Some(comment) => writeln!(writer, "{:<40} ; {}", definition, comment)?,
+            None => writeln!(writer, "{}", definition)?,
+        }
+
+        has_equates = true;
+
+    }
+
+    if has_equates{
+
+        writeln!(writer)?;
+
+    }
+
+    writeln!(writer, "        ORG {}\n", format_hex(origin, 4))?;
+
+    for line in lines{
+
+        let address = line.address();
+
+        let offset = address.wrapping_sub(origin) as usize;
+
+        match line {
+
+            Line::Code(instruction) => {
+
+                write_label(writer, formatter, address)?;
+
+                write_line(writer, &formatter.format(instruction), address, &rom[offset..offset + instruction.length as usize])?;
+
+                if !instruction.falls_through(){
+
+                    writeln!(writer)?;
+
+                }
+
+            },
+
+            Line::Data(_, bytes) => {
+
+                for (chunk_address, chunk) in split_at_labels(bytes, address, 6, formatter){
+
+                    write_label(writer, formatter, chunk_address)?;
+
+                    let values: Vec<String> = chunk.iter().map(|byte| format_hex(*byte as u16, 2)).collect();
+
+                    write_line(writer, &format!("DB {}", values.join(",")), chunk_address, chunk)?;
+
+                }
+
+            },
+
+            Line::Text(_, bytes) => {
+
+                for (chunk_address, chunk) in split_at_labels(bytes, address, 16, formatter){
+
+                    write_label(writer, formatter, chunk_address)?;
+
+                    write_line(writer, &format!("DB {}", format_text(chunk)), chunk_address, chunk)?;
+
+                }
+
+            },
+
+            Line::Words(_, words) => {
+
+                for (index, word) in words.iter().enumerate(){
+
+                    let value = match formatter.label(*word) {
+                        Some(label) => label.to_string(),
+                        None => format_hex(*word, 4),
+                    };
+
+                    let word_address = address.wrapping_add(index as u16 * 2);
+
+                    let word_offset = offset + index * 2;
+
+                    write_label(writer, formatter, word_address)?;
+
+                    write_line(writer, &format!("DW {}", value), word_address, &rom[word_offset..word_offset + 2])?;
+
+                }
+
+            },
+
+        }
+
+    }
+
+    writeln!(writer, "\n        END")
+
+}
+
+// Writes the label for an address, preceded by the symbol's comment if it has one
+fn write_label(writer: &mut dyn Write, formatter: &Formatter, address: u16) -> io::Result<()>{
+
+    if let Some(comment) = formatter.symbols.get(address).and_then(|symbol| symbol.comment.as_ref()){
+
+        writeln!(writer, "; {}", comment)?;
+
+    }
+
+    if let Some(label) = formatter.label(address){
+
+        writeln!(writer, "{}:", label)?;
+
+    }
+
+    Ok(())
+
+}
+
+// Splits data into chunks of at most the given size, starting a new chunk at every labelled address
+fn split_at_labels<'a>(bytes: &'a [u8], address: u16, size: usize, formatter: &Formatter) -> Vec<(u16, &'a [u8])>{
+
+    let mut chunks = vec![];
+
+    let mut start = 0;
+
+    for index in 1..=bytes.len(){
+
+        if index == bytes.len() || index - start == size || formatter.label(address.wrapping_add(index as u16)).is_some(){
+
+            chunks.push((address.wrapping_add(start as u16), &bytes[start..index]));
+
+            start = index;
+
+        }
+
+    }
+
+    chunks
+
+}
+
+// Printable characters are quoted, anything else is listed as a hexadecimal byte, e.g. 'SCORE',0DH
+fn format_text(bytes: &[u8]) -> String{
+
+    let mut values = vec![];
+
+    let mut quoted = String::new();
+
+    for byte in bytes{
+
+        if byte.is_ascii_graphic() && *byte != b'\'' || *byte == b' '{
+
+            quoted.push(*byte as char);
+
+        }
+        else{
+
+            if !quoted.is_empty(){
+
+                values.push(format!("'{}'", quoted));
+
+                quoted.clear();
+
+            }
+
+            values.push(format_hex(*byte as u16, 2));
+
+        }
+
+    }
+
+    if !quoted.is_empty(){
+
+        values.push(format!("'{}'", quoted));
+
+    }
+
+    values.join(",")
+
+}
+
+fn write_line(writer: &mut dyn Write, text: &str, address: u16, bytes: &[u8]) -> io::Result<()>{
+
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+    writeln!(writer, "        {:<32} ; {:04X}  {}", text, address, bytes.join(" "))
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn labels_jump_and_call_targets(){
+
+        let source = "
+                    ORG 0
+            START:  CALL SUB        ; 0000
+                    JZ 0004H        ; 0003, into its own operand
+                    NOP             ; 0006
+                    NOP             ; 0007
+            SUB:    RET             ; 0008
+                    RST 1           ; 0009, to SUB
+                    JMP START       ; 000A
+                    MVI A,08H       ; 000D, data rather than a target
+        ";
+
+        let (origin, rom) = assemble(source).expect("Failed to assemble").to_binary();
+
+        let lines = linear_sweep(&rom, origin);
+
+        let labels = generate_labels(&lines);
+
+        let mut addresses: Vec<(u16, &str)> = labels.iter().map(|(address, label)| (*address, label.as_str())).collect();
+
+        addresses.sort();
+
+        assert_eq!(addresses, vec![(0x0000, "L0000"), (0x0008, "L0008")]);
+
+        let formatter = Formatter{ labels, ..Default::default() };
+
+        let mut listing = vec![];
+
+        write_listing(&rom, origin, &lines, &formatter, &mut listing).expect("Failed to write listing");
+
+        let listing = String::from_utf8(listing).expect("Listing is not UTF-8");
+
+        for text in ["L0000:", "CALL L0008", "JZ 0004H", "L0008:", "RST 1", "JMP L0000", "MVI A,08H"].iter(){
+
+            assert!(listing.contains(text), "'{}' is missing from the listing:\n{}", text, listing);
+
+        }
+
+    }
+
+    #[test]
+    fn cut_off_instruction_is_data(){
+
+        let lines = linear_sweep(&[0x00, 0xCD, 0x34], 0x0100); // NOP, then a CALL missing its last byte
+
+        assert_eq!(lines.len(), 2);
+
+        match (&lines[0], &lines[1]) {
+            (Line::Code(instruction), Line::Data(address, bytes)) => {
+                assert_eq!((instruction.mnemonic, *address, bytes.clone()), ("NOP", 0x0101, vec![0xCD, 0x34]));
+            },
+            _ => panic!("Expected a line of code then a line of data"),
+        }
+
+    }
+
+}
@@ -0,0 +1,274 @@
+use crate::cpu::parse_address;
+
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymbolKind{
+    Code,
+    Byte,
+    Word,
+    Text,
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol{
+    pub name: String,
+    pub kind: SymbolKind,
+    pub count: u16, // Number of bytes, words or characters for data symbols
+    pub comment: Option<String>,
+}
+
+impl Symbol{
+
+    // Number of bytes a data symbol covers, code symbols have no length
+    pub fn data_length(&self) -> Option<u16>{
+
+        match self.kind {
+            SymbolKind::Code => None,
+            SymbolKind::Byte | SymbolKind::Text => Some(self.count),
+            SymbolKind::Word => Some(self.count.saturating_mul(2)),
+        }
+
+    }
+
+}
+
+#[derive(Clone, Default)]
+pub struct SymbolTable{
+    symbols: BTreeMap<u16, Symbol>,
+}
+
+impl SymbolTable{
+
+    pub fn load(file_name: &str) -> Result<SymbolTable, String>{
+
+        let text = std::fs::read_to_string(file_name).map_err(|_| format!("Unable to read symbol file '{}'", file_name))?;
+
+        SymbolTable::parse(&text).map_err(|error| format!("{}: {}", file_name, error))
+
+    }
+
+    // Each line has the format: <address> <name> [code|byte|word|text[*<count>]] [; comment]
+    // Lines starting with ';' are ignored, the kind defaults to code and the count to 1
+    pub fn parse(text: &str) -> Result<SymbolTable, String>{
+
+        let mut table = SymbolTable::default();
+
+        for (line_index, line) in text.lines().enumerate(){
+
+            let (definition, comment) = match line.find(';') {
+
+                Some(index) => (&line[..index], Some(line[index + 1..].trim().to_string())),
+
+                None => (line, None),
+
+            };
+
+            let fields: Vec<&str> = definition.split_whitespace().collect();
+
+            if fields.is_empty(){
+
+                continue;
+
+            }
+
+            let error = |message: String| format!("line {}: {}", line_index + 1, message);
+
+            if fields.len() < 2{
+
+                return Err(error("Expected <address> <name>".to_string()));
+
+            }
+
+            let address = parse_address(fields[0]).map_err(error)?;
+
+            let name = fields[1];
+
+            if !is_valid_name(name){
+
+                return Err(error(format!("Invalid symbol name '{}'", name)));
+
+            }
+
+            let (kind, count) = match fields.get(2) {
+
+                Some(kind) => {
+
+                    let mut sections = kind.splitn(2, '*');
+
+                    let kind = match sections.next().unwrap_or("").to_lowercase().as_str() {
+                        "code" => SymbolKind::Code,
+                        "byte" => SymbolKind::Byte,
+                        "word" => SymbolKind::Word,
+                        "text" => SymbolKind::Text,
+                        other => return Err(error(format!("Unknown symbol kind '{}'", other))),
+                    };
+
+                    let count = match sections.next() {
+                        Some(count) => count.parse::<u16>().map_err(|_| error(format!("Invalid count '{}'", count)))?,
+                        None => 1,
+                    };
+
+                    (kind, count)
+
+                },
+
+                None => (SymbolKind::Code, 1),
+
+            };
+
+            table.symbols.insert(address, Symbol{
+                name: name.to_string(),
+                kind,
+                count,
+                comment: comment.filter(|comment| !comment.is_empty()),
+            });
+
+        }
+
+        Ok(table)
+
+    }
+
+    pub fn get(&self, address: u16) -> Option<&Symbol>{
+
+        self.symbols.get(&address)
+
+    }
+
+    pub fn name(&self, address: u16) -> Option<&str>{
+
+        self.symbols.get(&address).map(|symbol| symbol.name.as_str())
+
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Symbol)>{
+
+        self.symbols.iter().map(|(address, symbol)| (*address, symbol))
+
+    }
+
+    // Resolves a hexadecimal address, or a symbol name if the text is not one, so a symbol called e.g. BEEF can't hide that address
+    pub fn resolve(&self, text: &str) -> Result<u16, String>{
+
+        let text = text.trim();
+
+        if let Ok(address) = parse_address(text){
+
+            return Ok(address);
+
+        }
+
+        match self.symbols.iter().find(|(_, symbol)| symbol.name.eq_ignore_ascii_case(text)) {
+
+            Some((address, _)) => Ok(*address),
+
+            None => Err(format!("'{}' is not a symbol or an address", text)),
+
+        }
+
+    }
+
+    // Describes an address relative to the closest code symbol before it, e.g. DrawScore+0x3
+    pub fn describe(&self, address: u16) -> Option<String>{
+
+        let (symbol_address, symbol) = self.symbols.range(..=address)
+            .rev()
+            .find(|(_, symbol)| symbol.kind == SymbolKind::Code)?;
+
+        if *symbol_address == address{
+
+            Some(symbol.name.clone())
+
+        }
+        else{
+
+            Some(format!("{}+0x{:x}", symbol.name, address - symbol_address))
+
+        }
+
+    }
+
+}
+
+// Names must be usable as assembler labels
+fn is_valid_name(name: &str) -> bool{
+
+    name.starts_with(|character: char| character.is_ascii_alphabetic() || character == '_' || character == '?' || character == '@')
+        && name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '?' || character == '@')
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+
+    #[test]
+    fn parses_symbol_files(){
+
+        let text = "
+            ; Routines
+            1925 DrawScore          ; Draws both scores
+            20f8 Score word
+            1a00 Message text*12    ;
+            2100 Flags BYTE*4
+        ";
+
+        let table = SymbolTable::parse(text).expect("Failed to parse symbols");
+
+        let symbol = table.get(0x1925).expect("DrawScore is missing");
+
+        assert_eq!((symbol.name.as_str(), symbol.kind, symbol.comment.as_deref()), ("DrawScore", SymbolKind::Code, Some("Draws both scores")));
+
+        assert_eq!(table.get(0x20F8).map(|symbol| (symbol.kind, symbol.data_length())), Some((SymbolKind::Word, Some(2))));
+
+        assert_eq!(table.get(0x1A00).map(|symbol| (symbol.kind, symbol.data_length(), symbol.comment.clone())), Some((SymbolKind::Text, Some(12), None)));
+
+        assert_eq!(table.get(0x2100).map(|symbol| (symbol.kind, symbol.count)), Some((SymbolKind::Byte, 4)));
+
+        assert_eq!(table.describe(0x1927), Some("DrawScore+0x2".to_string()));
+
+        assert_eq!(table.describe(0x1000), None);
+
+    }
+
+    #[test]
+    fn rejects_bad_lines(){
+
+        let errors = [
+            ("1925", "line 1: Expected <address> <name>"),
+            ("1925 DrawScore\nxyz Score", "line 2: Invalid address 'xyz'"),
+            ("10000 Big", "line 1: Invalid address '10000'"),
+            ("1925 1stScore", "line 1: Invalid symbol name '1stScore'"),
+            ("1925 Draw-Score", "line 1: Invalid symbol name 'Draw-Score'"),
+            ("20f8 Score long", "line 1: Unknown symbol kind 'long'"),
+            ("20f8 Score word*two", "line 1: Invalid count 'two'"),
+        ];
+
+        for (text, error) in errors.iter(){
+
+            assert_eq!(SymbolTable::parse(text).err().as_deref(), Some(*error), "Parsing '{}'", text);
+
+        }
+
+    }
+
+    #[test]
+    fn resolves_addresses_before_names(){
+
+        let table = SymbolTable::parse("1925 DrawScore\n2000 BEEF\n3000 C000\n").expect("Failed to parse symbols");
+
+        assert_eq!(table.resolve("drawscore"), Ok(0x1925));
+
+        assert_eq!(table.resolve(" 0x1925 "), Ok(0x1925));
+
+        assert_eq!(table.resolve("BEEF"), Ok(0xBEEF)); // Not shadowed by the symbols of the same name
+
+        assert_eq!(table.resolve("C000"), Ok(0xC000));
+
+        assert!(table.resolve("Nowhere").is_err());
+
+    }
+
+}
@@ -0,0 +1,136 @@
+// Renders 8080 instructions with the equivalent Z80 mnemonics, e.g. MOV A,M as LD A,(HL) and JNZ as JP NZ
+use super::{format_hex, Flow, Formatter, Instruction, Operand};
+
+pub fn format(formatter: &Formatter, instruction: &Instruction) -> String{
+
+    let operand = |index: usize| match instruction.operands.get(index) {
+        Some(operand) => format_operand(formatter, operand),
+        None => String::new(),
+    };
+
+    let indirect = |index: usize| format!("({})", operand(index));
+
+    let (mnemonic, operands): (&str, Vec<String>) = match instruction.mnemonic {
+
+        "MOV" | "MVI" | "LXI" => ("LD", vec![operand(0), operand(1)]),
+        "LDA" => ("LD", vec!["A".to_string(), indirect(0)]),
+        "STA" => ("LD", vec![indirect(0), "A".to_string()]),
+        "LHLD" => ("LD", vec!["HL".to_string(), indirect(0)]),
+        "SHLD" => ("LD", vec![indirect(0), "HL".to_string()]),
+        "LDAX" => ("LD", vec!["A".to_string(), indirect(0)]),
+        "STAX" => ("LD", vec![indirect(0), "A".to_string()]),
+        "SPHL" => ("LD", vec!["SP".to_string(), "HL".to_string()]),
+        "XCHG" => ("EX", vec!["DE".to_string(), "HL".to_string()]),
+        "XTHL" => ("EX", vec!["(SP)".to_string(), "HL".to_string()]),
+        "PCHL" => ("JP", vec!["(HL)".to_string()]),
+
+        "ADD" | "ADI" => ("ADD", vec!["A".to_string(), operand(0)]),
+        "ADC" | "ACI" => ("ADC", vec!["A".to_string(), operand(0)]),
+        "SBB" | "SBI" => ("SBC", vec!["A".to_string(), operand(0)]),
+        "SUB" | "SUI" => ("SUB", vec![operand(0)]),
+        "ANA" | "ANI" => ("AND", vec![operand(0)]),
+        "XRA" | "XRI" => ("XOR", vec![operand(0)]),
+        "ORA" | "ORI" => ("OR", vec![operand(0)]),
+        "CMP" | "CPI" => ("CP", vec![operand(0)]),
+        "INR" | "INX" => ("INC", vec![operand(0)]),
+        "DCR" | "DCX" => ("DEC", vec![operand(0)]),
+        "DAD" => ("ADD", vec!["HL".to_string(), operand(0)]),
+
+        "CMA" => ("CPL", vec![]),
+        "STC" => ("SCF", vec![]),
+        "CMC" => ("CCF", vec![]),
+        "RLC" => ("RLCA", vec![]),
+        "RRC" => ("RRCA", vec![]),
+        "RAL" => ("RLA", vec![]),
+        "RAR" => ("RRA", vec![]),
+        "HLT" => ("HALT", vec![]),
+
+        "IN" => ("IN", vec!["A".to_string(), indirect(0)]),
+        "OUT" => ("OUT", vec![indirect(0), "A".to_string()]),
+
+        "JMP" => ("JP", vec![operand(0)]),
+        "RST" => match instruction.operands.first() {
+            Some(Operand::Restart(vector)) => ("RST", vec![format_hex(*vector as u16 * 8, 2)]),
+            _ => ("RST", vec![]),
+        },
+
+        // Conditional jumps, calls and returns keep the 8080 condition after the first letter, e.g. JNZ, CPE, RM
+        mnemonic if matches!(instruction.flow, Flow::ConditionalJump | Flow::ConditionalCall | Flow::ConditionalReturn) => {
+
+            let condition = mnemonic[1..].to_string();
+
+            match instruction.flow {
+                Flow::ConditionalJump => ("JP", vec![condition, operand(0)]),
+                Flow::ConditionalCall => ("CALL", vec![condition, operand(0)]),
+                _ => ("RET", vec![condition]),
+            }
+
+        },
+
+        // NOP, DAA, EI, DI, CALL, RET, PUSH, POP and data bytes are unchanged apart from register names
+        mnemonic => (mnemonic, instruction.operands.iter().map(|operand| format_operand(formatter, operand)).collect()),
+
+    };
+
+    if operands.is_empty(){
+
+        mnemonic.to_string()
+
+    }
+    else{
+
+        format!("{} {}", mnemonic, operands.join(","))
+
+    }
+
+}
+
+fn format_operand(formatter: &Formatter, operand: &Operand) -> String{
+
+    match *operand {
+        Operand::Register("M") => "(HL)".to_string(),
+        Operand::RegisterPair("B") => "BC".to_string(),
+        Operand::RegisterPair("D") => "DE".to_string(),
+        Operand::RegisterPair("H") => "HL".to_string(),
+        Operand::RegisterPair("PSW") => "AF".to_string(),
+        _ => formatter.format_operand(operand),
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::disassembler::{decode, Syntax};
+
+    #[test]
+    fn zilog_mnemonics(){
+
+        let formatter = Formatter{
+            syntax: Syntax::Zilog,
+            ..Default::default()
+        };
+
+        let expected = [
+            (vec![0x7E], "LD A,(HL)"),
+            (vec![0xC2, 0x34, 0x12], "JP NZ,1234H"),
+            (vec![0xF2, 0x34, 0x12], "JP P,1234H"),
+            (vec![0xEC, 0x34, 0x12], "CALL PE,1234H"),
+            (vec![0xF8], "RET M"),
+            (vec![0x3A, 0x34, 0x12], "LD A,(1234H)"),
+            (vec![0x09], "ADD HL,BC"),
+            (vec![0xF5], "PUSH AF"),
+            (vec![0xD3, 0x01], "OUT (01H),A"),
+            (vec![0xFF], "RST 38H"),
+        ];
+
+        for (bytes, text) in expected{
+
+            assert_eq!(formatter.format(&decode(&bytes, 0).expect("Failed to decode")), text);
+
+        }
+
+    }
+
+}
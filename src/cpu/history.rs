@@ -0,0 +1,117 @@
+// A ring buffer of the most recently executed instructions, dumped when something goes wrong
+use super::disassembler::Formatter;
+use super::trace::format_line;
+use super::Registers;
+
+use std::io::{self, Write};
+
+pub const DEFAULT_HISTORY_SIZE: usize = 4096;
+
+#[derive(Clone, Copy)]
+pub struct Entry{
+    pub total_cycles: u64,
+    pub registers: Registers, // Before the instruction executed
+    pub bytes: [u8; 3], // The opcode and the bytes after it, as they were when it executed
+}
+
+pub struct History{
+    entries: Vec<Entry>,
+    capacity: usize,
+    next: usize, // Index the next entry is written to once the buffer is full
+}
+
+impl History{
+
+    pub fn new(capacity: usize) -> History{
+
+        History{
+            entries: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+
+    }
+
+    pub fn is_enabled(&self) -> bool{
+
+        self.capacity > 0
+
+    }
+
+    pub fn record(&mut self, entry: Entry){
+
+        if self.entries.len() < self.capacity{
+
+            self.entries.push(entry);
+
+        }
+        else{
+
+            self.entries[self.next] = entry;
+
+            self.next = (self.next + 1) % self.capacity;
+
+        }
+
+    }
+
+    // Entries from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &Entry>{
+
+        self.entries[self.next..].iter().chain(self.entries[..self.next].iter())
+
+    }
+
+    // Writes the newest count entries, oldest first
+    pub fn write(&self, count: usize, formatter: &Formatter, writer: &mut dyn Write) -> io::Result<()>{
+
+        for entry in self.iter().skip(self.entries.len().saturating_sub(count)){
+
+            writeln!(writer, "{}", format_line(entry.total_cycles, &entry.registers, &entry.bytes, formatter))?;
+
+        }
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::Processor8080;
+    use crate::machine::AudioController;
+
+    #[test]
+    fn keeps_the_newest_entries(){
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        processor.memory = vec![0x3C, 0x3C, 0x3C, 0x3C, 0x3C, 0x00, 0x00, 0x00]; // INR A five times
+        processor.history = History::new(3);
+
+        for _ in 0..5{
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        let addresses: Vec<u16> = processor.history.iter().map(|entry| entry.registers.program_counter).collect();
+
+        assert_eq!(addresses, vec![2, 3, 4]);
+
+        let registers: Vec<u8> = processor.history.iter().map(|entry| entry.registers.a).collect();
+
+        assert_eq!(registers, vec![2, 3, 4]); // Registers are recorded before each instruction
+
+        let mut output = vec![];
+
+        processor.history.write(1, &Formatter::default(), &mut output).expect("Failed to write history");
+
+        assert!(String::from_utf8(output).expect("History is not UTF-8").starts_with("          20 0004  3C       INR A"));
+
+    }
+
+}
@@ -0,0 +1,138 @@
+// Devices attached to ranges of I/O ports, for machines whose hardware keeps state the input and output handlers can't
+// IN and OUT go to the device holding the port, and to the machine's handlers only if no device does
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+// The instruction a device places on the data bus when the CPU acknowledges its interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Acknowledge{
+    Restart(u8), // RST n
+    Call(u16), // As the 8259 supplies
+}
+
+pub trait Device{
+
+    // The port is relative to the first port of the device's range
+    fn input(&mut self, port: u8) -> u8;
+
+    fn output(&mut self, port: u8, value: u8, memory: &mut [u8]);
+
+    // Called once memory is loaded, before the first instruction executes
+    fn reset(&mut self, _memory: &mut [u8]){}
+
+    // Called with the cycles each instruction takes, for devices that count time
+    fn tick(&mut self, _cycles: u32){}
+
+    // Called while the CPU has interrupts enabled, a device asking for an interrupt returns the instruction it places on the bus
+    fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        None
+
+    }
+
+}
+
+// A device shared with the board, so other chips can drive its inputs, e.g. an interrupt controller's request lines
+impl<T: Device> Device for Rc<RefCell<T>>{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        self.borrow_mut().input(port)
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, memory: &mut [u8]){
+
+        self.borrow_mut().output(port, value, memory);
+
+    }
+
+    fn reset(&mut self, memory: &mut [u8]){
+
+        self.borrow_mut().reset(memory);
+
+    }
+
+    fn tick(&mut self, cycles: u32){
+
+        self.borrow_mut().tick(cycles);
+
+    }
+
+    fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        self.borrow_mut().acknowledge()
+
+    }
+
+}
+
+#[derive(Default)]
+pub struct IoMap{
+    devices: Vec<(RangeInclusive<u8>, Box<dyn Device>)>,
+}
+
+impl IoMap{
+
+    pub fn attach(&mut self, ports: RangeInclusive<u8>, device: Box<dyn Device>){
+
+        self.devices.push((ports, device));
+
+    }
+
+    pub fn input(&mut self, port: u8) -> Option<u8>{
+
+        let (ports, device) = self.devices.iter_mut().find(|(ports, _)| ports.contains(&port))?;
+
+        Some(device.input(port - ports.start()))
+
+    }
+
+    // Returns false if no device holds the port
+    pub fn output(&mut self, port: u8, value: u8, memory: &mut [u8]) -> bool{
+
+        match self.devices.iter_mut().find(|(ports, _)| ports.contains(&port)) {
+
+            Some((ports, device)) => {
+
+                device.output(port - ports.start(), value, memory);
+
+                true
+
+            },
+
+            None => false,
+
+        }
+
+    }
+
+    pub fn tick(&mut self, cycles: u32){
+
+        for (_, device) in self.devices.iter_mut(){
+
+            device.tick(cycles);
+
+        }
+
+    }
+
+    // Devices attached first have the highest priority
+    pub fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        self.devices.iter_mut().find_map(|(_, device)| device.acknowledge())
+
+    }
+
+    pub fn reset(&mut self, memory: &mut [u8]){
+
+        for (_, device) in self.devices.iter_mut(){
+
+            device.reset(memory);
+
+        }
+
+    }
+
+}
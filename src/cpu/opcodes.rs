@@ -0,0 +1,148 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Flow{
+    Sequential,
+    Jump,
+    ConditionalJump,
+    IndirectJump, // PCHL, the target is only known at runtime
+    Call,
+    ConditionalCall,
+    Return,
+    ConditionalReturn,
+    Restart,
+    Halt,
+}
+
+#[derive(Clone, Copy)]
+pub enum OperandKind{
+    Register(&'static str),
+    RegisterPair(&'static str),
+    Data8,
+    Data16,
+    Address,
+    Port,
+    Restart(u8),
+    Opcode, // Undocumented opcodes are displayed as a data byte
+}
+
+pub struct OpcodeInfo{
+    pub mnemonic: &'static str,
+    pub operands: &'static [OperandKind],
+    pub length: u8,
+    pub cycles: u8,
+    pub branch_cycles: Option<u8>, // Cycles taken when a conditional call or return is taken
+    pub flow: Flow,
+}
+
+// Generated by build.rs from tools/disassembler/opcodes.txt
+include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::Processor8080;
+    use crate::cpu::history::History;
+    use crate::cpu::disassembler::decode;
+    use crate::machine::AudioController;
+
+    // Cycles of each opcode as counted by hand, with conditional calls and returns not taken
+    const EXPECTED_CYCLES: [u8; 256] = [
+    //  0   1   2   3   4   5   6   7   8   9   A   B   C   D   E   F <- Lowest order bits
+        4,  10, 7,  5,  5,  5,  7,  4,  4,  10, 7,  5,  5,  5,  7,  4,  // 0
+        4,  10, 7,  5,  5,  5,  7,  4,  4,  10, 7,  5,  5,  5,  7,  4,  // 1
+        4,  10, 16, 5,  5,  5,  7,  4,  4,  10, 16, 5,  5,  5,  7,  4,  // 2
+        4,  10, 13, 5,  10, 10, 10, 4,  4,  10, 13, 5,  5,  5,  7,  4,  // 3
+        5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,  // 4
+        5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,  // 5
+        5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,  // 6
+        7,  7,  7,  7,  7,  7,  7,  7,  5,  5,  5,  5,  5,  5,  7,  5,  // 7
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // 8
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // 9
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // A
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,  // B
+        5,  10, 10, 10, 11, 11, 7,  11, 5,  10, 10, 10, 11, 11, 7,  11, // C
+        5,  10, 10, 10, 11, 11, 7,  11, 5,  10, 10, 10, 11, 11, 7,  11, // D
+        5,  10, 10, 18, 11, 11, 7,  11, 5,  5,  10, 5,  11, 11, 7,  11, // E
+        5,  10, 10, 4,  11, 11, 7,  11, 5,  5,  10, 4,  11, 11, 7,  11  // F
+    ];
+
+    // Executes every opcode with the flags all clear and all set, so each condition is both met and not, and checks the CPU
+    // consumes as many bytes as the disassembler reports and as many cycles as expected
+    #[test]
+    fn cpu_and_disassembler_agree(){
+
+        for opcode in 0..=255u8{
+
+            let instruction = decode(&[opcode, 0x34, 0x12], 0x1000).expect("Failed to decode");
+
+            let mut branches_taken = vec![];
+
+            for flags in [0x02, 0xD7].iter(){
+
+                let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+                processor.memory = vec![0; 0x10000];
+                processor.history = History::new(0); // Undocumented opcodes would otherwise dump the history to standard output
+                processor.memory[0x1000] = opcode;
+                processor.memory[0x1001] = 0x34;
+                processor.memory[0x1002] = 0x12;
+                processor.memory[0x2000] = 0x78; // Return address on the stack
+                processor.memory[0x2001] = 0x56;
+
+                let mut registers = processor.registers();
+
+                registers.program_counter = 0x1000;
+                registers.stack_pointer = 0x2000;
+                registers.h = 0x15;
+                registers.l = 0x00;
+                registers.flags = *flags;
+
+                processor.set_registers(&registers);
+
+                processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+                let fallthrough = 0x1000 + instruction.length as u16;
+
+                let expected_addresses = match instruction.flow {
+                    Flow::Sequential | Flow::Halt => vec![fallthrough],
+                    Flow::Jump | Flow::Call => vec![0x1234],
+                    Flow::ConditionalJump | Flow::ConditionalCall => vec![fallthrough, 0x1234],
+                    Flow::Return => vec![0x5678],
+                    Flow::ConditionalReturn => vec![fallthrough, 0x5678],
+                    Flow::IndirectJump => vec![0x1500],
+                    Flow::Restart => vec![(opcode & 0b00111000) as u16],
+                };
+
+                assert!(
+                    expected_addresses.contains(&processor.program_counter),
+                    "Opcode 0x{:02x} ({}) moved the program counter to 0x{:04x}, expected one of {:04x?}",
+                    opcode, instruction.mnemonic, processor.program_counter, expected_addresses
+                );
+
+                let taken = processor.program_counter != fallthrough;
+
+                let expected_cycles = match instruction.flow {
+                    Flow::ConditionalCall if taken => 17,
+                    Flow::ConditionalReturn if taken => 11,
+                    _ => EXPECTED_CYCLES[opcode as usize],
+                };
+
+                assert_eq!(processor.cycles_elapsed, expected_cycles as u16, "Opcode 0x{:02x} ({}) cycles with flags {:02x}", opcode, instruction.mnemonic, flags);
+
+                branches_taken.push(taken);
+
+            }
+
+            if let Flow::ConditionalJump | Flow::ConditionalCall | Flow::ConditionalReturn = instruction.flow{
+
+                assert!(branches_taken.contains(&true) && branches_taken.contains(&false), "Opcode 0x{:02x} ({}) should branch with only one set of flags", opcode, instruction.mnemonic);
+
+            }
+
+            assert_eq!(instruction.cycles, EXPECTED_CYCLES[opcode as usize], "Opcode 0x{:02x} ({}) cycles", opcode, instruction.mnemonic);
+
+        }
+
+    }
+
+}
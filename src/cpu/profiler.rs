@@ -0,0 +1,332 @@
+// Counts instructions and cycles per address and builds a call tree from CALL, RST, RET and interrupts
+// so the time spent in each subroutine, including the routines it calls, can be reported
+use super::disassembler::Formatter;
+use super::opcodes::{Flow, OPCODE_TABLE};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+const HOT_SPOTS: usize = 40; // Number of addresses listed in the flat report
+const MIN_TREE_SHARE: f64 = 0.001; // Call tree nodes below this share of the total are left out
+
+// A routine reached by a particular chain of calls
+struct Node{
+    routine: Option<u16>, // None for the root, which holds everything executed outside a call
+    calls: u64,
+    self_cycles: u64,
+    children: BTreeMap<u16, usize>, // Routine address to node index
+}
+
+// A call that has not returned yet
+struct Frame{
+    node: usize,
+    stack_pointer: u16, // After the return address was pushed, the frame is over once the stack is above it
+}
+
+pub struct Profiler{
+    file_name: String,
+    instructions: Vec<u64>, // Indexed by address
+    cycles: Vec<u64>,
+    nodes: Vec<Node>,
+    stack: Vec<Frame>,
+    pub frames: u64, // Frames drawn by the machine, used to show the average cost of each routine per frame
+}
+
+impl Profiler{
+
+    pub fn new(file_name: &str) -> Profiler{
+
+        Profiler{
+            file_name: file_name.to_string(),
+            instructions: vec![0; 0x10000],
+            cycles: vec![0; 0x10000],
+            nodes: vec![Node{ routine: None, calls: 0, self_cycles: 0, children: BTreeMap::new() }],
+            stack: vec![],
+            frames: 0,
+        }
+
+    }
+
+    // Called after each instruction with the program counter and stack pointer it left behind, and the cycles it took
+    pub fn record(&mut self, address: u16, opcode: u8, program_counter: u16, stack_pointer: u16, cycles: u64){
+
+        let info = &OPCODE_TABLE[opcode as usize];
+
+        self.instructions[address as usize] += 1;
+
+        self.cycles[address as usize] += cycles;
+
+        let node = self.current_node();
+
+        self.nodes[node].self_cycles += cycles;
+
+        let next_address = address.wrapping_add(info.length as u16);
+
+        match info.flow {
+
+            Flow::Call | Flow::ConditionalCall | Flow::Restart if program_counter != next_address => {
+
+                self.leave(stack_pointer.wrapping_add(1)); // Calls made after the stack was unwound past a frame end it
+
+                self.enter(node, program_counter, stack_pointer);
+
+            },
+
+            Flow::Return | Flow::ConditionalReturn if program_counter != next_address => self.leave(stack_pointer),
+
+            _ => {},
+
+        }
+
+    }
+
+    // Interrupt handlers are attributed to the top of the tree rather than whichever routine was interrupted
+    pub fn interrupt(&mut self, vector: u16, stack_pointer: u16, cycles: u64){
+
+        self.leave(stack_pointer.wrapping_add(1));
+
+        self.enter(0, vector, stack_pointer);
+
+        let node = self.current_node();
+
+        self.nodes[node].self_cycles += cycles;
+
+    }
+
+    fn current_node(&self) -> usize{
+
+        self.stack.last().map_or(0, |frame| frame.node)
+
+    }
+
+    fn enter(&mut self, parent: usize, routine: u16, stack_pointer: u16){
+
+        let node = match self.nodes[parent].children.get(&routine) {
+
+            Some(node) => *node,
+
+            None => {
+
+                self.nodes.push(Node{ routine: Some(routine), calls: 0, self_cycles: 0, children: BTreeMap::new() });
+
+                let node = self.nodes.len() - 1;
+
+                self.nodes[parent].children.insert(routine, node);
+
+                node
+
+            },
+
+        };
+
+        self.nodes[node].calls += 1;
+
+        self.stack.push(Frame{ node, stack_pointer });
+
+    }
+
+    // Ends every frame whose return address is below the stack pointer
+    fn leave(&mut self, stack_pointer: u16){
+
+        while self.stack.last().is_some_and(|frame| frame.stack_pointer < stack_pointer){
+
+            self.stack.pop();
+
+        }
+
+    }
+
+    // Self cycles of the node plus every routine it called
+    fn total_cycles(&self, node: usize, totals: &mut Vec<u64>) -> u64{
+
+        let total = self.nodes[node].self_cycles + self.nodes[node].children.values().map(|child| self.total_cycles(*child, totals)).sum::<u64>();
+
+        totals[node] = total;
+
+        total
+
+    }
+
+    pub fn write_to_file(&self, formatter: &Formatter) -> io::Result<()>{
+
+        let mut writer = BufWriter::new(File::create(&self.file_name)?);
+
+        self.write(formatter, &mut writer)?;
+
+        writer.flush()
+
+    }
+
+    pub fn write(&self, formatter: &Formatter, writer: &mut dyn Write) -> io::Result<()>{
+
+        let mut totals = vec![0; self.nodes.len()];
+
+        let total = self.total_cycles(0, &mut totals);
+
+        let share = |cycles: u64| if total == 0 { 0.0 } else { cycles as f64 * 100.0 / total as f64 };
+
+        let per_frame = |cycles: u64| cycles.checked_div(self.frames).map_or(String::new(), |cycles| format!("{:>12}", cycles));
+
+        let routine_name = |routine: Option<u16>| match routine {
+            Some(address) => formatter.label(address).map_or_else(|| format!("{:04X}", address), |name| name.to_string()),
+            None => "(top level)".to_string(),
+        };
+
+        writeln!(writer, "{} cycles, {} instructions, {} frames", total, self.instructions.iter().sum::<u64>(), self.frames)?;
+
+        // Recursive calls are only counted once towards a routine's total
+        let mut routines: BTreeMap<Option<u16>, (u64, u64, u64)> = BTreeMap::new();
+
+        let mut pending = vec![(0, vec![])];
+
+        while let Some((node, ancestors)) = pending.pop(){
+
+            let routine = self.nodes[node].routine;
+
+            let entry = routines.entry(routine).or_default();
+
+            entry.0 += self.nodes[node].calls;
+
+            entry.1 += self.nodes[node].self_cycles;
+
+            if !ancestors.contains(&routine){
+
+                entry.2 += totals[node];
+
+            }
+
+            for child in self.nodes[node].children.values(){
+
+                let mut ancestors = ancestors.clone();
+
+                ancestors.push(routine);
+
+                pending.push((*child, ancestors));
+
+            }
+
+        }
+
+        let mut routines: Vec<(Option<u16>, (u64, u64, u64))> = routines.into_iter().collect();
+
+        routines.sort_by_key(|(_, (_, self_cycles, _))| std::cmp::Reverse(*self_cycles));
+
+        writeln!(writer, "\nRoutines by self cycles:")?;
+
+        writeln!(writer, "{:>12} {:>6} {:>12} {:>6} {:>10}{} Routine", "Self", "%", "Total", "%", "Calls", if self.frames == 0 { "" } else { "   Per frame" })?;
+
+        for (routine, (calls, self_cycles, total_cycles)) in routines{
+
+            writeln!(writer, "{:>12} {:>6.2} {:>12} {:>6.2} {:>10}{} {}",
+                self_cycles, share(self_cycles), total_cycles, share(total_cycles), calls, per_frame(total_cycles), routine_name(routine)
+            )?;
+
+        }
+
+        let mut addresses: Vec<usize> = (0..self.cycles.len()).filter(|address| self.cycles[*address] > 0).collect();
+
+        addresses.sort_by(|a, b| self.cycles[*b].cmp(&self.cycles[*a]));
+
+        writeln!(writer, "\nHot spots:")?;
+
+        writeln!(writer, "{:>12} {:>6} {:>12} Address", "Cycles", "%", "Executed")?;
+
+        for address in addresses.into_iter().take(HOT_SPOTS){
+
+            let location = formatter.symbols.describe(address as u16).map_or(String::new(), |location| format!(" {}", location));
+
+            writeln!(writer, "{:>12} {:>6.2} {:>12} {:04X}{}", self.cycles[address], share(self.cycles[address]), self.instructions[address], address, location)?;
+
+        }
+
+        writeln!(writer, "\nCall tree (total cycles, self cycles, calls):")?;
+
+        let mut pending = vec![(0, 0)];
+
+        while let Some((node, depth)) = pending.pop(){
+
+            writeln!(writer, "{:>12} {:>6.2} {:>12} {:>10} {}{}",
+                totals[node], share(totals[node]), self.nodes[node].self_cycles, self.nodes[node].calls, "  ".repeat(depth), routine_name(self.nodes[node].routine)
+            )?;
+
+            let mut children: Vec<usize> = self.nodes[node].children.values()
+                .copied()
+                .filter(|child| share(totals[*child]) >= MIN_TREE_SHARE * 100.0)
+                .collect();
+
+            children.sort_by(|a, b| totals[*a].cmp(&totals[*b])); // Popped in reverse, so the largest is written first
+
+            pending.extend(children.into_iter().map(|child| (child, depth + 1)));
+
+        }
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+    use crate::cpu::Processor8080;
+    use crate::machine::AudioController;
+
+    #[test]
+    fn attributes_cycles_to_routines(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H    ; 0000
+            START:  CALL OUTER      ; 0003
+                    JMP START       ; 0006
+            OUTER:  CALL INNER      ; 0009
+                    RET             ; 000C
+            INNER:  NOP             ; 000D
+                    RET             ; 000E
+        ";
+
+        let (_, rom) = assemble(source).expect("Failed to assemble").to_binary();
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        processor.memory = rom;
+        processor.memory.resize(0x200, 0);
+        processor.profiler = Some(Profiler::new("unused"));
+
+        for _ in 0..1 + 6 * 2{ // LXI, then two passes of the loop
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        let profiler = processor.profiler.as_ref().expect("Profiler was set");
+
+        assert_eq!(profiler.instructions[0x0D], 2);
+
+        assert!(profiler.stack.is_empty());
+
+        let outer = profiler.nodes[0].children[&0x0009];
+
+        let inner = profiler.nodes[outer].children[&0x000D];
+
+        assert_eq!((profiler.nodes[outer].calls, profiler.nodes[outer].self_cycles), (2, 2 * (11 + 10)));
+
+        assert_eq!((profiler.nodes[inner].calls, profiler.nodes[inner].self_cycles), (2, 2 * (4 + 10)));
+
+        let mut output = vec![];
+
+        profiler.write(&Formatter::default(), &mut output).expect("Failed to write profile");
+
+        let output = String::from_utf8(output).expect("Profile is not UTF-8");
+
+        assert!(output.starts_with("122 cycles, 13 instructions, 0 frames"));
+
+        assert!(output.contains("          42  34.43           70  57.38          2 0009\n"));
+
+    }
+
+}
@@ -0,0 +1,207 @@
+// Writes one line per instruction in a format that can be diffed against other emulators:
+// <total cycles> <PC>  <opcode bytes> <mnemonic> AF=.... BC=.... DE=.... HL=.... SP=....
+use super::disassembler::{decode, Formatter};
+use super::disassembler::symbols::SymbolTable;
+use super::Registers;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TraceCondition{
+    Address(u16), // The instruction at this address is about to execute
+    Cycle(u64), // The total cycle count has reached this value
+}
+
+impl TraceCondition{
+
+    // Format: <address or symbol> or cycle:<decimal count>, e.g. 1925 or cycle:2000000
+    pub fn parse(specification: &str, symbols: &SymbolTable) -> Result<TraceCondition, String>{
+
+        match specification.trim().strip_prefix("cycle:") {
+
+            Some(count) => count.trim().parse::<u64>()
+                .map(TraceCondition::Cycle)
+                .map_err(|_| format!("Invalid cycle count '{}'", count)),
+
+            None => symbols.resolve(specification).map(TraceCondition::Address),
+
+        }
+
+    }
+
+    fn is_met(&self, program_counter: u16, total_cycles: u64) -> bool{
+
+        match *self {
+            TraceCondition::Address(address) => program_counter == address,
+            TraceCondition::Cycle(cycle) => total_cycles >= cycle,
+        }
+
+    }
+
+}
+
+pub struct Trace{
+    writer: Box<dyn Write>,
+    pub start: Option<TraceCondition>, // Tracing begins immediately if not set
+    pub stop: Option<TraceCondition>, // The instruction that meets the condition is not traced
+    pub filters: Vec<(u16, u16)>, // Inclusive address ranges, only instructions within them are traced if any are given
+    started: bool,
+    stopped: bool,
+    formatter: Formatter, // Plain Intel mnemonics without symbols, so traces from different setups can be compared
+}
+
+impl Trace{
+
+    pub fn to_file(file_name: &str) -> Result<Trace, String>{
+
+        let file = File::create(file_name).map_err(|_| format!("Unable to create trace file '{}'", file_name))?;
+
+        Ok(Trace{
+            writer: Box::new(BufWriter::new(file)),
+            start: None,
+            stop: None,
+            filters: vec![],
+            started: false,
+            stopped: false,
+            formatter: Formatter::default(),
+        })
+
+    }
+
+    // Format: <start>-<end>, addresses or symbols
+    pub fn parse_filter(specification: &str, symbols: &SymbolTable) -> Result<(u16, u16), String>{
+
+        let (start, end) = match specification.find('-') {
+            Some(index) => (symbols.resolve(&specification[..index])?, symbols.resolve(&specification[index + 1..])?),
+            None => return Err(format!("Invalid trace filter '{}', expected <start>-<end>", specification)),
+        };
+
+        if end < start{
+
+            return Err(format!("Trace filter {:04x}-{:04x} ends before it starts", start, end));
+
+        }
+
+        Ok((start, end))
+
+    }
+
+    // Called before each instruction executes, with the cycle count before it
+    pub fn record(&mut self, registers: &Registers, memory: &[u8], total_cycles: u64) -> io::Result<()>{
+
+        let program_counter = registers.program_counter;
+
+        if self.stopped{
+
+            return Ok(());
+
+        }
+
+        if !self.started{
+
+            match self.start {
+                Some(condition) if !condition.is_met(program_counter, total_cycles) => return Ok(()),
+                _ => self.started = true,
+            }
+
+        }
+
+        if let Some(condition) = self.stop{
+
+            if condition.is_met(program_counter, total_cycles){
+
+                self.stopped = true;
+
+                return self.writer.flush();
+
+            }
+
+        }
+
+        if !self.filters.is_empty() && !self.filters.iter().any(|(start, end)| program_counter >= *start && program_counter <= *end){
+
+            return Ok(());
+
+        }
+
+        let mut bytes = [0; 3];
+
+        for (offset, byte) in bytes.iter_mut().enumerate(){
+
+            *byte = memory.get(program_counter as usize + offset).copied().unwrap_or(0);
+
+        }
+
+        writeln!(self.writer, "{}", format_line(total_cycles, registers, &bytes, &self.formatter))
+
+    }
+
+    pub fn flush(&mut self) -> io::Result<()>{
+
+        self.writer.flush()
+
+    }
+
+}
+
+// Formats the instruction starting at bytes with the registers before it executes
+pub fn format_line(total_cycles: u64, registers: &Registers, bytes: &[u8; 3], formatter: &Formatter) -> String{
+
+    let instruction = decode(bytes, registers.program_counter).expect("Failed to decode an instruction"); // Always decodes, with three bytes
+
+    let bytes: Vec<String> = bytes.iter().take(instruction.length as usize).map(|byte| format!("{:02X}", byte)).collect();
+
+    format!("{:>12} {:04X}  {:<8} {:<16} AF={:02X}{:02X} BC={:02X}{:02X} DE={:02X}{:02X} HL={:02X}{:02X} SP={:04X}",
+        total_cycles, registers.program_counter, bytes.join(" "), formatter.format(&instruction),
+        registers.a, registers.flags, registers.b, registers.c, registers.d, registers.e, registers.h, registers.l, registers.stack_pointer
+    )
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::Processor8080;
+    use crate::machine::AudioController;
+
+    #[test]
+    fn start_stop_and_filters(){
+
+        let file_name = std::env::temp_dir().join("emulator_8080_trace_test.txt").to_string_lossy().into_owned();
+
+        let mut trace = Trace::to_file(&file_name).expect("Failed to create trace");
+
+        trace.start = Some(TraceCondition::Address(0x0002)); // Skip the first MVI
+        trace.stop = Some(TraceCondition::Cycle(60));
+        trace.filters = vec![(0x0000, 0x0005)]; // Skip the NOPs and the JMP back
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        // 0000: MVI A,01H; INR A; JMP 0008H
+        // 0008: NOP; NOP; JMP 0000H
+        processor.memory = vec![0x3E, 0x01, 0x3C, 0xC3, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC3, 0x00, 0x00, 0x00, 0x00, 0x00];
+        processor.trace = Some(trace);
+
+        for _ in 0..12{
+
+            processor.emulate(&mut vec![0; 256], &mut AudioController::default());
+
+        }
+
+        processor.flush_output();
+
+        let output = std::fs::read_to_string(&file_name).expect("Failed to read trace");
+
+        assert_eq!(output, concat!(
+            "           7 0002  3C       INR A            AF=0102 BC=0000 DE=0000 HL=0000 SP=0000\n",
+            "          12 0003  C3 08 00 JMP 0008H        AF=0212 BC=0000 DE=0000 HL=0000 SP=0000\n",
+            "          40 0000  3E 01    MVI A,01H        AF=0212 BC=0000 DE=0000 HL=0000 SP=0000\n",
+            "          47 0002  3C       INR A            AF=0112 BC=0000 DE=0000 HL=0000 SP=0000\n",
+            "          52 0003  C3 08 00 JMP 0008H        AF=0212 BC=0000 DE=0000 HL=0000 SP=0000\n",
+        ));
+
+    }
+
+}
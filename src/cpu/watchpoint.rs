@@ -0,0 +1,291 @@
+use std::fmt;
+
+use super::disassembler::symbols::SymbolTable;
+use super::hex_digits;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AccessKind{
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchAction{
+    Log, // Write the access and the program counter to the logger
+    Break, // Pause emulation and enter the debugger
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint{
+    pub start: u16,
+    pub end: u16, // Inclusive
+    pub kind: AccessKind,
+    pub value: Option<u8>, // If set, only trigger when this value is read, written or executed
+    pub action: WatchAction,
+}
+
+impl Watchpoint{
+
+    // Format: <read|write|execute>:<start>[-<end>][=<value>][:<log|break>], e.g. write:20f8-20f9=05:break
+    // Addresses may also be symbol names, e.g. execute:DrawScore:break
+    pub fn parse(specification: &str, symbols: &SymbolTable) -> Result<Watchpoint, String>{
+
+        let mut sections = specification.trim().split(':');
+
+        let kind = match sections.next().unwrap_or("").to_lowercase().as_str() {
+
+            "r" | "read" => AccessKind::Read,
+
+            "w" | "write" => AccessKind::Write,
+
+            "x" | "execute" => AccessKind::Execute,
+
+            other => return Err(format!("Unknown watchpoint access kind '{}'", other)),
+
+        };
+
+        let range = sections.next().ok_or_else(|| "Missing watchpoint address".to_string())?;
+
+        let (range, value) = match range.find('=') {
+
+            Some(index) => (&range[..index], Some(parse_byte(&range[index + 1..])?)),
+
+            None => (range, None),
+
+        };
+
+        let (start, end) = match range.find('-') {
+
+            Some(index) => (symbols.resolve(&range[..index])?, symbols.resolve(&range[index + 1..])?),
+
+            None => {
+                let address = symbols.resolve(range)?;
+                (address, address)
+            },
+
+        };
+
+        if end < start{
+
+            return Err(format!("Watchpoint range {:04x}-{:04x} ends before it starts", start, end));
+
+        }
+
+        let action = match sections.next().map(|action| action.to_lowercase()).as_deref() {
+
+            None | Some("log") => WatchAction::Log,
+
+            Some("break") => WatchAction::Break,
+
+            Some(other) => return Err(format!("Unknown watchpoint action '{}'", other)),
+
+        };
+
+        Ok(Watchpoint{
+            start,
+            end,
+            kind,
+            value,
+            action,
+        })
+
+    }
+
+    pub fn matches(&self, kind: AccessKind, address: u16, value: u8) -> bool{
+
+        let value_matches = match self.value {
+            Some(expected) => expected == value,
+            None => true,
+        };
+
+        self.kind == kind && address >= self.start && address <= self.end && value_matches
+
+    }
+
+}
+
+impl fmt::Display for Watchpoint{
+
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result{
+
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+            AccessKind::Execute => "execute",
+        };
+
+        write!(formatter, "{}:{:04x}", kind, self.start)?;
+
+        if self.end != self.start{
+
+            write!(formatter, "-{:04x}", self.end)?;
+
+        }
+
+        if let Some(value) = self.value{
+
+            write!(formatter, "={:02x}", value)?;
+
+        }
+
+        match self.action {
+            WatchAction::Log => write!(formatter, ":log"),
+            WatchAction::Break => write!(formatter, ":break"),
+        }
+
+    }
+
+}
+
+fn parse_byte(text: &str) -> Result<u8, String>{
+
+    let text = text.trim();
+
+    u8::from_str_radix(hex_digits(text), 16).map_err(|_| format!("Invalid byte value '{}'", text))
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::testing::{load_program, run};
+
+    #[test]
+    fn parses_specifications(){
+
+        let symbols = SymbolTable::parse("0004 LOOP\n").expect("Failed to parse symbols");
+
+        let parse = |specification: &str| Watchpoint::parse(specification, &symbols).map(|watchpoint| watchpoint.to_string());
+
+        assert_eq!(parse("write:20f8"), Ok("write:20f8:log".to_string()));
+
+        assert_eq!(parse("w:20F8-0x20F9=0X05:BREAK"), Ok("write:20f8-20f9=05:break".to_string()));
+
+        assert_eq!(parse("r:0X1000=ff"), Ok("read:1000=ff:log".to_string()));
+
+        assert_eq!(parse("execute:loop:break"), Ok("execute:0004:break".to_string()));
+
+        assert_eq!(parse("x:LOOP-0010"), Ok("execute:0004-0010:log".to_string()));
+
+        for specification in ["", "jump:1000", "read", "read:", "read:12345", "read:2000-1000", "read:1000=100", "read:1000=", "read:1000:stop", "read:nowhere"].iter(){
+
+            assert!(parse(specification).is_err(), "'{}' should not parse", specification);
+
+        }
+
+    }
+
+    #[test]
+    fn matches_accesses(){
+
+        let watchpoint = Watchpoint{
+            start: 0x2000,
+            end: 0x2001,
+            kind: AccessKind::Write,
+            value: Some(0x05),
+            action: WatchAction::Log,
+        };
+
+        assert!(watchpoint.matches(AccessKind::Write, 0x2000, 0x05));
+
+        assert!(watchpoint.matches(AccessKind::Write, 0x2001, 0x05));
+
+        assert!(!watchpoint.matches(AccessKind::Write, 0x2002, 0x05)); // Outside the range
+
+        assert!(!watchpoint.matches(AccessKind::Write, 0x1FFF, 0x05));
+
+        assert!(!watchpoint.matches(AccessKind::Write, 0x2000, 0x06)); // Another value
+
+        assert!(!watchpoint.matches(AccessKind::Read, 0x2000, 0x05)); // Another kind of access
+
+        let any_value = Watchpoint{ value: None, ..watchpoint };
+
+        assert!(any_value.matches(AccessKind::Write, 0x2000, 0x06));
+
+    }
+
+    #[test]
+    fn breaks_and_resumes(){
+
+        let source = "
+                    ORG 0
+                    MVI A,05H
+                    STA 2000H
+            LOOP:   INR A
+                    JMP LOOP
+        ";
+
+        let mut processor = load_program(source, vec![]);
+
+        let symbols = SymbolTable::parse("0005 LOOP\n").expect("Failed to parse symbols");
+
+        processor.watchpoints = vec![
+            Watchpoint::parse("write:2000=05:break", &symbols).expect("Failed to parse watchpoint"),
+            Watchpoint::parse("execute:LOOP:break", &symbols).expect("Failed to parse watchpoint"),
+        ];
+
+        run(&mut processor, 2);
+
+        assert!(processor.take_break().expect("The write should break").starts_with("Watchpoint 0"));
+
+        assert_eq!(processor.registers().program_counter, 0x0005); // After the write
+
+        run(&mut processor, 1);
+
+        assert!(processor.take_break().expect("The execute should break").starts_with("Watchpoint 1"));
+
+        assert_eq!((processor.registers().program_counter, processor.registers().a), (0x0005, 0x05)); // Before INR A
+
+        run(&mut processor, 1); // Resumes without breaking again
+
+        assert_eq!(processor.take_break(), None);
+
+        assert_eq!((processor.registers().program_counter, processor.registers().a), (0x0006, 0x06));
+
+        run(&mut processor, 2); // JMP LOOP, then LOOP again
+
+        assert!(processor.take_break().is_some());
+
+    }
+
+    #[test]
+    fn watches_exchanges_with_the_stack(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,2000H
+                    LXI H,1234H
+                    XTHL
+                    XTHL
+                    XTHL
+        ";
+
+        let mut processor = load_program(source, vec![]);
+
+        processor.memory[0x2000..0x2002].copy_from_slice(&[0x78, 0x56]);
+
+        processor.watchpoints = vec![Watchpoint::parse("write:2001=12:break", &SymbolTable::default()).expect("Failed to parse watchpoint")];
+
+        run(&mut processor, 3);
+
+        assert!(processor.take_break().expect("The exchange should break").starts_with("Watchpoint 0"));
+
+        assert_eq!((processor.registers().h, processor.registers().l), (0x56, 0x78));
+
+        assert_eq!(&processor.memory[0x2000..0x2002], &[0x34, 0x12]);
+
+        processor.watchpoints.clear();
+
+        processor.read_only.push(0x2000..0x2002);
+
+        run(&mut processor, 1); // The stack is now read only, so only HL changes
+
+        assert_eq!((processor.registers().h, processor.registers().l), (0x12, 0x34));
+
+        assert_eq!(&processor.memory[0x2000..0x2002], &[0x34, 0x12]);
+
+    }
+
+}
@@ -0,0 +1,11 @@
+// Peripheral chips of 8080 systems, attached to a machine's I/O map so boards built from them can be put together without bespoke handlers
+pub mod pic;
+pub mod pit;
+pub mod ppi;
+pub mod usart;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+// The level of a wire between chips, shared by whatever drives it and whatever reads it
+pub type Line = Rc<Cell<bool>>;
@@ -0,0 +1,537 @@
+// The Intel 8259 programmable interrupt controller in 8080 mode, on a pair of ports: command words written to the first start
+// initialization (ICW1) or are operation commands (OCW2 and OCW3), the second takes the rest of initialization and the mask (OCW1)
+// When the CPU acknowledges an interrupt the controller supplies a CALL to the vector of the highest priority request
+// The board drives the eight request lines with request, e.g. from a timer's output callback
+use crate::cpu::io_map::{Acknowledge, Device};
+
+// ICW1 bits
+const INITIALIZE: u8 = 0x10;
+const NEEDS_ICW4: u8 = 0x01;
+const SINGLE: u8 = 0x02;
+const INTERVAL_4: u8 = 0x04;
+const LEVEL_TRIGGERED: u8 = 0x08;
+
+// ICW4 and OCW3 bits
+const AUTO_EOI: u8 = 0x02;
+const OCW3: u8 = 0x08;
+const POLL: u8 = 0x04;
+const READ_REGISTER: u8 = 0x02;
+
+// The next write to the second port
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expecting{
+    Icw2,
+    Icw3,
+    Icw4,
+    Mask,
+}
+
+pub struct Pic{
+    icw1: u8,
+    vector: u8, // ICW2, the high byte of every vector
+    expecting: Expecting,
+    initialized: bool, // Nothing is acknowledged until the first initialization has finished
+    auto_eoi: bool,
+    rotate_on_auto_eoi: bool,
+    special_mask: bool,
+    read_in_service: bool, // Which register OCW3 selected for reads of the first port
+    poll: bool, // The next read of the first port is a poll
+    requests: u8, // IRR
+    in_service: u8, // ISR
+    mask: u8, // IMR
+    levels: u8, // Of the request lines
+    lowest: u8, // The request line with the lowest priority, the one after it has the highest
+}
+
+impl Pic{
+
+    pub fn new() -> Self{
+
+        Pic{
+            icw1: 0,
+            vector: 0,
+            expecting: Expecting::Mask,
+            initialized: false,
+            auto_eoi: false,
+            rotate_on_auto_eoi: false,
+            special_mask: false,
+            read_in_service: false,
+            poll: false,
+            requests: 0,
+            in_service: 0,
+            mask: 0,
+            levels: 0,
+            lowest: 7,
+        }
+
+    }
+
+    // Sets the level of a request line, a request is made by its rising edge or, if so initialized, while it's high
+    pub fn request(&mut self, line: u8, level: bool){
+
+        let bit = 1 << line;
+
+        let rising = level && self.levels & bit == 0;
+
+        self.levels = if level { self.levels | bit } else { self.levels & !bit };
+
+        if rising || (level && self.icw1 & LEVEL_TRIGGERED != 0){
+
+            self.requests |= bit;
+
+        }
+        else if !level && self.icw1 & LEVEL_TRIGGERED != 0{
+
+            self.requests &= !bit;
+
+        }
+
+    }
+
+    // Request lines from the highest priority to the lowest
+    fn by_priority(&self) -> impl Iterator<Item = u8>{
+
+        let lowest = self.lowest;
+
+        (1..=8).map(move |offset| (lowest + offset) % 8)
+
+    }
+
+    // The request to interrupt with, which must have a higher priority than any in service, except those masked in the special mask mode
+    fn highest_request(&self) -> Option<u8>{
+
+        let requests = self.requests & !self.mask;
+
+        for line in self.by_priority(){
+
+            let bit = 1 << line;
+
+            if requests & bit != 0 && self.in_service & bit == 0{
+
+                return Some(line);
+
+            }
+
+            if self.in_service & bit != 0 && !(self.special_mask && self.mask & bit != 0){
+
+                return None;
+
+            }
+
+        }
+
+        None
+
+    }
+
+    // The INTA sequence, or a poll, puts the request in service
+    fn take(&mut self, line: u8){
+
+        let bit = 1 << line;
+
+        if self.icw1 & LEVEL_TRIGGERED == 0{
+
+            self.requests &= !bit;
+
+        }
+
+        if !self.auto_eoi{
+
+            self.in_service |= bit;
+
+        }
+        else if self.rotate_on_auto_eoi{
+
+            self.lowest = line;
+
+        }
+
+    }
+
+    fn vector_address(&self, line: u8) -> u16{
+
+        let low = match self.icw1 & INTERVAL_4 {
+            0 => (self.icw1 & 0xC0) | (line << 3),
+            _ => (self.icw1 & 0xE0) | (line << 2),
+        };
+
+        ((self.vector as u16) << 8) | low as u16
+
+    }
+
+    fn initialize(&mut self, icw1: u8){
+
+        *self = Pic{
+            icw1,
+            vector: self.vector,
+            expecting: Expecting::Icw2,
+            levels: self.levels,
+            requests: if icw1 & LEVEL_TRIGGERED != 0 { self.levels } else { 0 }, // The edge sense latches are reset
+            ..Pic::new()
+        };
+
+    }
+
+    fn end_of_interrupt(&mut self, command: u8){
+
+        let rotate = command & 0x80 != 0;
+
+        let line = match command & 0x60 {
+
+            0x20 => match self.by_priority().find(|line| self.in_service & (1 << line) != 0) { // Non-specific
+                Some(line) => line,
+                None => return,
+            },
+
+            0x60 => command & 0x07, // Specific
+
+            _ => return,
+
+        };
+
+        self.in_service &= !(1 << line);
+
+        if rotate{
+
+            self.lowest = line;
+
+        }
+
+    }
+
+    fn write_operation(&mut self, value: u8){
+
+        if value & OCW3 != 0{
+
+            if value & 0x40 != 0{
+
+                self.special_mask = value & 0x20 != 0;
+
+            }
+
+            self.poll = value & POLL != 0;
+
+            if value & READ_REGISTER != 0{
+
+                self.read_in_service = value & 0x01 != 0;
+
+            }
+
+            return;
+
+        }
+
+        match value >> 5 { // OCW2
+
+            0 | 4 => self.rotate_on_auto_eoi = value & 0x80 != 0,
+
+            6 => self.lowest = value & 0x07, // Set priority
+
+            2 => {}, // No operation
+
+            _ => self.end_of_interrupt(value),
+
+        }
+
+    }
+
+    fn write_initialization(&mut self, value: u8){
+
+        self.expecting = match self.expecting {
+
+            Expecting::Icw2 => {
+
+                self.vector = value;
+
+                match self.icw1 {
+                    icw1 if icw1 & SINGLE == 0 => Expecting::Icw3,
+                    icw1 if icw1 & NEEDS_ICW4 != 0 => Expecting::Icw4,
+                    _ => Expecting::Mask,
+                }
+
+            },
+
+            Expecting::Icw3 if self.icw1 & NEEDS_ICW4 != 0 => Expecting::Icw4, // Cascading, with no slaves to address
+
+            Expecting::Icw3 => Expecting::Mask,
+
+            Expecting::Icw4 => {
+
+                self.auto_eoi = value & AUTO_EOI != 0; // 8086 mode and buffering don't apply to an 8080
+
+                Expecting::Mask
+
+            },
+
+            Expecting::Mask => {
+
+                self.mask = value;
+
+                Expecting::Mask
+
+            },
+
+        };
+
+        self.initialized |= self.expecting == Expecting::Mask;
+
+    }
+
+}
+
+impl Device for Pic{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        match port {
+
+            0 if self.poll => {
+
+                self.poll = false;
+
+                match self.highest_request() {
+
+                    Some(line) => {
+
+                        self.take(line);
+
+                        0x80 | line
+
+                    },
+
+                    None => 0,
+
+                }
+
+            },
+
+            0 if self.read_in_service => self.in_service,
+
+            0 => self.requests,
+
+            _ => self.mask,
+
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        match port {
+            0 if value & INITIALIZE != 0 => self.initialize(value),
+            0 => self.write_operation(value),
+            _ => self.write_initialization(value),
+        }
+
+    }
+
+    fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        if !self.initialized{
+
+            return None;
+
+        }
+
+        let line = self.highest_request()?;
+
+        self.take(line);
+
+        Some(Acknowledge::Call(self.vector_address(line)))
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::testing::{load_program, run};
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn calls_vectors_by_priority(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H
+                    LXI H,0200H
+                    MVI A,12H       ; ICW1: edge triggered, single, vectors 8 bytes apart
+                    OUT 0D8H
+                    MVI A,10H       ; ICW2: vectors from 1000H
+                    OUT 0D9H
+                    MVI A,0F3H      ; OCW1: only IR2 and IR3
+                    OUT 0D9H
+                    EI
+            WAIT:   JMP WAIT
+                    ORG 1010H       ; IR2
+                    MVI A,'2'
+                    JMP SERVE
+                    ORG 1018H       ; IR3
+                    MVI A,'3'
+            SERVE:  MOV M,A
+                    INX H
+                    MVI A,20H       ; Non-specific EOI
+                    OUT 0D8H
+                    EI
+                    RET
+        ";
+
+        let pic = Rc::new(RefCell::new(Pic::new()));
+
+        let mut processor = load_program(source, vec![(0xD8..=0xD9, Box::new(Rc::clone(&pic)))]);
+
+        run(&mut processor, 20); // Initialized, which forgets earlier requests
+
+        for line in [3, 5, 2].iter(){ // IR5 is masked
+
+            pic.borrow_mut().request(*line, true);
+
+        }
+
+        run(&mut processor, 100);
+
+        assert_eq!(&processor.memory[0x0200..0x0203], b"23\0");
+
+        assert_eq!(pic.borrow_mut().input(0), 0x20); // IR5 is still requested
+
+    }
+
+    #[test]
+    fn wakes_the_cpu_from_a_halt(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H
+                    MVI A,12H       ; ICW1: edge triggered, single, vectors 8 bytes apart
+                    OUT 0D8H
+                    MVI A,10H       ; ICW2: vectors from 1000H
+                    OUT 0D9H
+                    MVI A,0FBH      ; OCW1: only IR2
+                    OUT 0D9H
+                    EI
+                    HLT
+                    MVI A,'w'       ; Returned to after the interrupt
+                    STA 0201H
+                    DI
+                    HLT             ; Only a reset leaves this
+                    ORG 1010H       ; IR2
+                    MVI A,'2'
+                    STA 0200H
+                    MVI A,20H       ; Non-specific EOI
+                    OUT 0D8H
+                    EI
+                    RET
+        ";
+
+        let pic = Rc::new(RefCell::new(Pic::new()));
+
+        let mut processor = load_program(source, vec![(0xD8..=0xD9, Box::new(Rc::clone(&pic)))]);
+
+        run(&mut processor, 20);
+
+        assert!(processor.halted());
+
+        let total_cycles = processor.total_cycles;
+
+        run(&mut processor, 5);
+
+        assert_eq!(processor.total_cycles - total_cycles, 20); // Idles for 4 cycles at a time
+
+        pic.borrow_mut().request(2, true);
+
+        run(&mut processor, 20);
+
+        assert_eq!(&processor.memory[0x0200..0x0202], b"2w");
+
+        assert!(processor.halted());
+
+        processor.reset();
+
+        assert!(!processor.halted());
+
+        assert_eq!(processor.registers().program_counter, 0);
+
+    }
+
+    #[test]
+    fn modes_and_commands(){
+
+        let mut pic = Pic::new();
+
+        let mut memory = [];
+
+        for (port, value) in [(0, 0x17), (1, 0x20), (1, 0x02), (1, 0x00)].iter(){ // Interval 4, ICW4 with automatic EOI
+
+            pic.output(*port, *value, &mut memory);
+
+        }
+
+        pic.request(6, true);
+
+        pic.request(1, true);
+
+        assert_eq!(pic.acknowledge(), Some(Acknowledge::Call(0x2004)));
+
+        assert_eq!(pic.acknowledge(), Some(Acknowledge::Call(0x2018))); // Nothing stays in service
+
+        assert_eq!(pic.acknowledge(), None);
+
+        pic.request(1, false);
+
+        pic.output(0, 0xC4, &mut memory); // IR4 has the lowest priority, so IR5 the highest
+
+        pic.request(6, false);
+
+        pic.request(6, true);
+
+        pic.request(3, false);
+
+        pic.request(3, true);
+
+        pic.output(0, 0x0C, &mut memory); // Poll
+
+        assert_eq!(pic.input(0), 0x86);
+
+        pic.output(0, 0x0A, &mut memory); // Read the IRR
+
+        assert_eq!(pic.input(0), 0x08);
+
+        for (port, value) in [(0, 0x1E), (1, 0x20), (1, 0x00)].iter(){ // Level triggered, without ICW4 so without automatic EOI
+
+            pic.output(*port, *value, &mut memory);
+
+        }
+
+        assert_eq!(pic.acknowledge(), Some(Acknowledge::Call(0x200C))); // IR3 and IR6 are still high, and IR7 has the lowest priority again
+
+        assert_eq!(pic.acknowledge(), None); // IR6 waits for IR3 to end
+
+        pic.output(0, 0x0B, &mut memory); // Read the ISR
+
+        assert_eq!(pic.input(0), 0x08);
+
+        pic.output(0, 0x63, &mut memory); // Specific EOI for IR3
+
+        pic.request(3, false);
+
+        assert_eq!(pic.acknowledge(), Some(Acknowledge::Call(0x2018)));
+
+        pic.request(7, true);
+
+        assert_eq!(pic.acknowledge(), None);
+
+        pic.output(0, 0x68, &mut memory); // Special mask mode
+
+        assert_eq!(pic.acknowledge(), None); // IR6 still blocks IR7 until it's masked
+
+        pic.output(1, 0x40, &mut memory); // OCW1: mask IR6, which lets the lower priority IR7 in
+
+        assert_eq!(pic.acknowledge(), Some(Acknowledge::Call(0x201C)));
+
+    }
+
+}
@@ -0,0 +1,650 @@
+// The Intel 8253 programmable interval timer: three 16 bit down counters on the first three ports of its four, and the control word on the last
+// Counters are clocked from the CPU's cycles, divided down as the board's clock is, and their gates are lines the board drives
+// A change of a counter's output calls its callback, and a rising edge can interrupt the CPU with an RST
+use crate::cpu::io_map::{Acknowledge, Device};
+use crate::devices::Line;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Called with the new level of an output
+pub type OutputCallback = Box<dyn FnMut(bool)>;
+
+pub struct Pit{
+    counters: [Counter; 3],
+    cycles_per_clock: u32, // CPU cycles for each pulse on the counters' clock inputs
+    cycles: u32, // Left over from the last tick
+    pub gates: [Line; 3], // High, so counting is enabled, unless the board connects them
+    pub on_output: [Option<OutputCallback>; 3],
+    pub interrupts: [Option<u8>; 3], // RST raised on the rising edge of each output
+    pending: [bool; 3], // Interrupts raised and not yet acknowledged
+}
+
+impl Pit{
+
+    pub fn new(cycles_per_clock: u32) -> Self{
+
+        Pit{
+            counters: Default::default(),
+            cycles_per_clock: cycles_per_clock.max(1),
+            cycles: 0,
+            gates: [Rc::new(Cell::new(true)), Rc::new(Cell::new(true)), Rc::new(Cell::new(true))],
+            on_output: Default::default(),
+            interrupts: [None; 3],
+            pending: [false; 3],
+        }
+
+    }
+
+    // Calls back and raises interrupts for outputs changed by the counter, be it by a clock pulse or a write
+    fn output_changed(&mut self, index: usize, before: bool){
+
+        let output = self.counters[index].output;
+
+        if output == before{
+
+            return;
+
+        }
+
+        if let Some(callback) = &mut self.on_output[index]{
+
+            callback(output);
+
+        }
+
+        if output && self.interrupts[index].is_some(){
+
+            self.pending[index] = true;
+
+        }
+
+    }
+
+}
+
+impl Device for Pit{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        match self.counters.get_mut(port as usize) {
+            Some(counter) => counter.read(),
+            None => 0xFF, // The control word can't be read back
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        let index = match port {
+            3 => (value >> 6) as usize,
+            _ => port as usize,
+        };
+
+        let counter = match self.counters.get_mut(index) {
+            Some(counter) => counter,
+            None => return, // Counter 3 is the read back command of the 8254
+        };
+
+        let before = counter.output;
+
+        match port {
+            3 => counter.control(value),
+            _ => counter.write(value),
+        }
+
+        self.output_changed(index, before);
+
+    }
+
+    fn tick(&mut self, cycles: u32){
+
+        self.cycles += cycles;
+
+        while self.cycles >= self.cycles_per_clock{
+
+            self.cycles -= self.cycles_per_clock;
+
+            for index in 0..3{
+
+                let before = self.counters[index].output;
+
+                let gate = self.gates[index].get();
+
+                self.counters[index].clock(gate);
+
+                self.output_changed(index, before);
+
+            }
+
+        }
+
+    }
+
+    fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        let index = self.pending.iter().position(|pending| *pending)?;
+
+        self.pending[index] = false;
+
+        self.interrupts[index].map(Acknowledge::Restart)
+
+    }
+
+}
+
+#[derive(Default)]
+struct Counter{
+    mode: u8,
+    access: u8, // 1 the low byte only, 2 the high byte only, 3 the low byte then the high byte
+    bcd: bool,
+    reload: u16, // The count register, as written
+    count: u32, // The counting element, in binary even when counting in BCD
+    high_byte_next: bool, // Of the count being written
+    read_high_byte_next: bool,
+    latch: Option<u16>,
+    load: bool, // A count was written, to be loaded on the next clock pulse
+    triggered: bool, // The gate rose, so the count is reloaded on the next clock pulse
+    counting: bool,
+    gate_was_low: bool, // At the last clock pulse
+    output: bool,
+    square_high: bool, // Which half of the square wave mode 3 is in
+}
+
+impl Counter{
+
+    // A count of 0 is the largest, 65536 in binary and 10000 in BCD
+    fn initial_count(&self) -> u32{
+
+        let count = if self.bcd { from_bcd(self.reload) } else { self.reload as u32 };
+
+        match count {
+            0 if self.bcd => 10000,
+            0 => 0x10000,
+            _ => count,
+        }
+
+    }
+
+    fn decrement(&mut self){
+
+        self.count = match self.count {
+            0 if self.bcd => 9999,
+            0 => 0xFFFF,
+            count => count - 1,
+        };
+
+    }
+
+    fn value(&self) -> u16{
+
+        let count = self.count % if self.bcd { 10000 } else { 0x10000 };
+
+        if self.bcd { to_bcd(count) } else { count as u16 }
+
+    }
+
+    fn control(&mut self, value: u8){
+
+        let access = (value >> 4) & 0x03;
+
+        if access == 0{ // Counter latch command
+
+            if self.latch.is_none(){
+
+                self.latch = Some(self.value());
+
+            }
+
+            return;
+
+        }
+
+        *self = Counter{
+            mode: match (value >> 1) & 0x07 { 6 => 2, 7 => 3, mode => mode },
+            access,
+            bcd: value & 0x01 != 0,
+            gate_was_low: self.gate_was_low,
+            output: (value >> 1) & 0x07 != 0, // Low in mode 0 until the count runs out, otherwise high
+            ..Counter::default()
+        };
+
+    }
+
+    fn write(&mut self, value: u8){
+
+        match self.access {
+
+            1 => self.reload = value as u16,
+
+            2 => self.reload = (value as u16) << 8,
+
+            _ if !self.high_byte_next => {
+
+                self.reload = (self.reload & 0xFF00) | value as u16;
+
+                self.high_byte_next = true;
+
+                if self.mode == 0{
+
+                    self.output = false;
+
+                    self.counting = false; // Until the whole count is written
+
+                }
+
+                return;
+
+            },
+
+            _ => {
+
+                self.reload = (self.reload & 0x00FF) | ((value as u16) << 8);
+
+                self.high_byte_next = false;
+
+            },
+
+        }
+
+        match self.mode {
+
+            0 => {
+
+                self.output = false;
+
+                self.load = true;
+
+            },
+
+            1 | 5 => {}, // Loaded when the gate rises
+
+            4 => self.load = true,
+
+            _ => self.load |= !self.counting, // Otherwise the new count is used from the next period
+
+        }
+
+    }
+
+    fn read(&mut self) -> u8{
+
+        let value = self.latch.unwrap_or_else(|| self.value());
+
+        let (byte, done) = match self.access {
+            1 => (value as u8, true),
+            2 => ((value >> 8) as u8, true),
+            _ if !self.read_high_byte_next => (value as u8, false),
+            _ => ((value >> 8) as u8, true),
+        };
+
+        self.read_high_byte_next = !done;
+
+        if done{
+
+            self.latch = None;
+
+        }
+
+        byte
+
+    }
+
+    fn clock(&mut self, gate: bool){
+
+        if gate && self.gate_was_low{
+
+            self.triggered = true;
+
+        }
+
+        self.gate_was_low = !gate;
+
+        match self.mode {
+
+            0 => self.clock_terminal_count(gate),
+
+            1 => self.clock_one_shot(),
+
+            2 => self.clock_rate_generator(gate),
+
+            3 => self.clock_square_wave(gate),
+
+            _ => self.clock_strobe(gate),
+
+        }
+
+    }
+
+    // Mode 0, the output rises when the count runs out and stays high
+    fn clock_terminal_count(&mut self, gate: bool){
+
+        if self.load{
+
+            self.load = false;
+
+            self.count = self.initial_count();
+
+            self.counting = true;
+
+        }
+        else if self.counting && gate{
+
+            self.decrement();
+
+            if self.count == 0{
+
+                self.output = true;
+
+            }
+
+        }
+
+    }
+
+    // Mode 1, the output is low from a rise of the gate until the count runs out, and a later rise starts again
+    fn clock_one_shot(&mut self){
+
+        if self.triggered{
+
+            self.triggered = false;
+
+            self.count = self.initial_count();
+
+            self.output = false;
+
+            self.counting = true;
+
+        }
+        else{
+
+            self.decrement();
+
+            if self.counting && self.count == 0{
+
+                self.output = true;
+
+                self.counting = false;
+
+            }
+
+        }
+
+    }
+
+    // Mode 2, the output is low for one pulse of every count, a low gate stops it with the output high
+    fn clock_rate_generator(&mut self, gate: bool){
+
+        if !gate{
+
+            self.output = true;
+
+        }
+        else if self.load || self.triggered{
+
+            self.load = false;
+
+            self.triggered = false;
+
+            self.count = self.initial_count();
+
+            self.counting = true;
+
+        }
+        else if self.counting{
+
+            if self.count == 1{
+
+                self.count = self.initial_count();
+
+                self.output = true;
+
+            }
+            else{
+
+                self.decrement();
+
+                self.output = self.count != 1;
+
+            }
+
+        }
+
+    }
+
+    // Mode 3, the output is high for the first half of every count and low for the second, the longer half being high when it's odd
+    fn clock_square_wave(&mut self, gate: bool){
+
+        if !gate{
+
+            self.output = true;
+
+        }
+        else if self.load || self.triggered{
+
+            self.load = false;
+
+            self.triggered = false;
+
+            self.square_high = true;
+
+            self.count = self.initial_count().div_ceil(2);
+
+            self.counting = true;
+
+        }
+        else if self.counting{
+
+            self.decrement();
+
+            if self.count == 0{
+
+                self.square_high = !self.square_high;
+
+                let count = self.initial_count();
+
+                self.count = if self.square_high { count.div_ceil(2) } else { count / 2 };
+
+                self.output = self.square_high;
+
+            }
+
+        }
+
+    }
+
+    // Modes 4 and 5, the output is low for one pulse when the count runs out, counting from a write in mode 4 or a rise of the gate in mode 5
+    fn clock_strobe(&mut self, gate: bool){
+
+        let started = if self.mode == 4 { self.load } else { self.triggered }; // A low gate only stops counting in mode 4
+
+        self.output = true;
+
+        if started{
+
+            self.load = false;
+
+            self.triggered = false;
+
+            self.count = self.initial_count();
+
+            self.counting = true;
+
+        }
+        else if self.counting && (gate || self.mode == 5){
+
+            self.decrement();
+
+            if self.count == 0{
+
+                self.output = false;
+
+                self.counting = false;
+
+            }
+
+        }
+
+    }
+
+}
+
+fn from_bcd(value: u16) -> u32{
+
+    (0..4).rev().fold(0, |total, digit| total * 10 + ((value >> (digit * 4)) & 0x0F) as u32)
+
+}
+
+fn to_bcd(value: u32) -> u16{
+
+    (0..4).fold(0, |total, digit| total | (((value / 10_u32.pow(digit)) % 10) as u16) << (digit * 4))
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::testing::run_program;
+
+    use std::cell::RefCell;
+
+    // Clocks the timer one pulse at a time and collects counter 0's output after each
+    fn outputs(pit: &mut Pit, pulses: usize) -> String{
+
+        (0..pulses).map(|_| {
+
+            pit.tick(1);
+
+            if pit.counters[0].output { '1' } else { '0' }
+
+        }).collect()
+
+    }
+
+    fn program(pit: &mut Pit, control: u8, count: &[u8]){
+
+        let mut memory = [];
+
+        pit.output(3, control, &mut memory);
+
+        for byte in count{
+
+            pit.output(0, *byte, &mut memory);
+
+        }
+
+    }
+
+    #[test]
+    fn counter_modes(){
+
+        let mut pit = Pit::new(1);
+
+        program(&mut pit, 0x10, &[3]); // Mode 0, low byte only
+
+        assert_eq!(outputs(&mut pit, 6), "000111");
+
+        program(&mut pit, 0x14, &[3]); // Mode 2
+
+        assert_eq!(outputs(&mut pit, 8), "11011011");
+
+        program(&mut pit, 0x16, &[5]); // Mode 3, odd
+
+        assert_eq!(outputs(&mut pit, 11), "11100111001");
+
+        program(&mut pit, 0x18, &[2]); // Mode 4
+
+        assert_eq!(outputs(&mut pit, 5), "11011");
+
+        pit.gates[0].set(false); // The count is loaded with the gate low, but not counted down until it rises
+
+        program(&mut pit, 0x18, &[2]);
+
+        assert_eq!(outputs(&mut pit, 3), "111");
+
+        pit.gates[0].set(true);
+
+        assert_eq!(outputs(&mut pit, 4), "1011");
+
+        assert_eq!(pit.counters[0].count, 0);
+
+        program(&mut pit, 0x12, &[2]); // Mode 1, waits for the gate
+
+        assert_eq!(outputs(&mut pit, 2), "11");
+
+        pit.gates[0].set(false);
+
+        pit.tick(1);
+
+        pit.gates[0].set(true);
+
+        assert_eq!(outputs(&mut pit, 4), "0011");
+
+        program(&mut pit, 0x1A, &[1]); // Mode 5
+
+        pit.gates[0].set(false);
+
+        assert_eq!(outputs(&mut pit, 2), "11");
+
+        pit.gates[0].set(true);
+
+        assert_eq!(outputs(&mut pit, 4), "1011");
+
+    }
+
+    #[test]
+    fn counts_latches_and_interrupts(){
+
+        let mut pit = Pit::new(2);
+
+        let changes = Rc::new(RefCell::new(vec![]));
+
+        let seen = Rc::clone(&changes);
+
+        pit.on_output[1] = Some(Box::new(move |level| seen.borrow_mut().push(level)));
+
+        pit.interrupts[1] = Some(7);
+
+        let mut memory = [];
+
+        pit.output(3, 0x71, &mut memory); // Counter 1, both bytes, mode 0, BCD
+
+        pit.output(1, 0x00, &mut memory);
+
+        pit.output(1, 0x10, &mut memory); // 1000
+
+        pit.tick(2 * 11); // Loaded, then counted down by 10
+
+        pit.output(3, 0x40, &mut memory); // Latch counter 1
+
+        pit.tick(2);
+
+        assert_eq!((pit.input(1), pit.input(1)), (0x90, 0x09)); // 990
+
+        assert_eq!(pit.acknowledge(), None);
+
+        pit.tick(2 * 990);
+
+        assert_eq!(pit.acknowledge(), Some(Acknowledge::Restart(7)));
+
+        assert_eq!(pit.acknowledge(), None);
+
+        assert_eq!(*changes.borrow(), vec![true]);
+
+    }
+
+    #[test]
+    fn ticked_by_each_instruction(){
+
+        let mut pit = Pit::new(1);
+
+        program(&mut pit, 0x10, &[100]);
+
+        let mut processor = run_program("", vec![(0xDC..=0xDF, Box::new(pit))], 10); // NOPs of 4 cycles
+
+        assert_eq!(processor.io.input(0xDC), Some(61)); // Loaded on the first clock pulse, then counted down on the other 39
+
+    }
+
+}
@@ -0,0 +1,497 @@
+// The Intel 8255 programmable peripheral interface: ports A, B and C on the first three ports of its four and the control word on the last
+// Whatever the board connects, e.g. a keypad or a display, is a Peripheral; in mode 1 and mode 2 it strobes bytes into ports A and B
+// and takes the bytes written at once, as if it acknowledged them straight away, while port C carries the handshake
+use crate::cpu::io_map::{Acknowledge, Device};
+use crate::devices::pit::OutputCallback;
+
+// What the board connects to the ports, numbered 0 for A, 1 for B and 2 for C
+pub trait Peripheral{
+
+    // The levels on the port's pins, read while they are inputs
+    fn read(&mut self, _port: usize) -> u8{
+
+        0xFF // Pulled up
+
+    }
+
+    // Called when an output port is written, for port C with only the bits that are outputs
+    fn write(&mut self, _port: usize, _value: u8){}
+
+    // A byte strobed into port A or B in mode 1 or 2, asked for while the input buffer is empty
+    fn strobe(&mut self, _port: usize) -> Option<u8>{
+
+        None
+
+    }
+
+}
+
+// For ports nothing is connected to
+pub struct Unconnected;
+
+impl Peripheral for Unconnected{}
+
+// Port C bits of the handshakes, for group A and group B
+const INTERRUPT: [u8; 2] = [0x08, 0x01]; // INTR
+const INPUT_FULL: [u8; 2] = [0x20, 0x02]; // IBF
+const INPUT_ENABLE: [u8; 2] = [0x10, 0x04]; // INTE, at STB
+const OUTPUT_EMPTY: [u8; 2] = [0x80, 0x02]; // OBF, active low
+const OUTPUT_ENABLE: [u8; 2] = [0x40, 0x04]; // INTE, at ACK
+
+pub struct Ppi{
+    peripheral: Box<dyn Peripheral>,
+    control: u8, // The mode word
+    latches: [u8; 3], // Output latches of ports A, B and C
+    inputs: [u8; 2], // Input latches of ports A and B, for strobed input
+    input_full: [bool; 2],
+    input_enable: [bool; 2],
+    output_enable: [bool; 2],
+    interrupting: [bool; 2], // INTR of groups A and B, as last seen
+    pub on_interrupt: [Option<OutputCallback>; 2], // Called when INTR of group A or B changes
+    pub interrupts: [Option<u8>; 2], // RST raised while INTR of group A or B is high
+}
+
+impl Ppi{
+
+    pub fn new(peripheral: Box<dyn Peripheral>) -> Self{
+
+        Ppi{
+            peripheral,
+            control: 0x9B, // Every port an input in mode 0, as after RESET
+            latches: [0; 3],
+            inputs: [0; 2],
+            input_full: [false; 2],
+            input_enable: [false; 2],
+            output_enable: [false; 2],
+            interrupting: [false; 2],
+            on_interrupt: Default::default(),
+            interrupts: [None; 2],
+        }
+
+    }
+
+    fn mode(&self, group: usize) -> u8{
+
+        match group {
+            0 => match (self.control >> 5) & 0x03 { 0 => 0, 1 => 1, _ => 2 },
+            _ => (self.control >> 2) & 0x01,
+        }
+
+    }
+
+    fn is_input(&self, port: usize) -> bool{
+
+        self.control & [0x10, 0x02][port] != 0
+
+    }
+
+    // Mode 2 is both
+    fn strobed_input(&self, group: usize) -> bool{
+
+        match self.mode(group) {
+            0 => false,
+            1 => self.is_input(group),
+            _ => true,
+        }
+
+    }
+
+    fn strobed_output(&self, group: usize) -> bool{
+
+        match self.mode(group) {
+            0 => false,
+            1 => !self.is_input(group),
+            _ => true,
+        }
+
+    }
+
+    // Port C bits given over to the handshake
+    fn handshake_bits(&self, group: usize) -> u8{
+
+        match (group, self.mode(group), self.is_input(group)) {
+            (_, 0, _) => 0,
+            (0, 1, true) => 0x38,
+            (0, 1, false) => 0xC8,
+            (0, _, _) => 0xF8,
+            _ => 0x07,
+        }
+
+    }
+
+    // Port C bits that are inputs from the peripheral, the rest are outputs unless they are part of a handshake
+    fn c_input_bits(&self) -> u8{
+
+        let upper = if self.control & 0x08 != 0 { 0xF0 } else { 0 };
+
+        let lower = if self.control & 0x01 != 0 { 0x0F } else { 0 };
+
+        (upper | lower) & !self.handshake_bits(0) & !self.handshake_bits(1)
+
+    }
+
+    fn c_output_bits(&self) -> u8{
+
+        !self.c_input_bits() & !self.handshake_bits(0) & !self.handshake_bits(1)
+
+    }
+
+    // Output is taken as soon as it's written, so only the input buffer and the enables decide INTR
+    fn interrupt(&self, group: usize) -> bool{
+
+        (self.strobed_input(group) && self.input_enable[group] && self.input_full[group]) ||
+        (self.strobed_output(group) && self.output_enable[group])
+
+    }
+
+    fn read_c(&mut self) -> u8{
+
+        let inputs = self.c_input_bits();
+
+        let mut value = (self.peripheral.read(2) & inputs) | (self.latches[2] & self.c_output_bits());
+
+        for group in 0..2{
+
+            if self.interrupt(group){
+
+                value |= INTERRUPT[group];
+
+            }
+
+            if self.strobed_input(group){
+
+                if self.input_full[group]{
+
+                    value |= INPUT_FULL[group];
+
+                }
+
+                if self.input_enable[group]{
+
+                    value |= INPUT_ENABLE[group];
+
+                }
+
+            }
+
+            if self.strobed_output(group){
+
+                value |= OUTPUT_EMPTY[group];
+
+                if self.output_enable[group]{
+
+                    value |= OUTPUT_ENABLE[group];
+
+                }
+
+            }
+
+        }
+
+        value
+
+    }
+
+    // Asks the peripheral for strobed input while there's room for it
+    fn poll(&mut self){
+
+        for group in 0..2{
+
+            if self.strobed_input(group) && !self.input_full[group]{
+
+                if let Some(value) = self.peripheral.strobe(group){
+
+                    self.inputs[group] = value;
+
+                    self.input_full[group] = true;
+
+                }
+
+            }
+
+        }
+
+    }
+
+    // Calls back for every change of INTR
+    fn update_interrupts(&mut self){
+
+        for group in 0..2{
+
+            let interrupting = self.interrupt(group);
+
+            if interrupting != self.interrupting[group]{
+
+                self.interrupting[group] = interrupting;
+
+                if let Some(callback) = &mut self.on_interrupt[group]{
+
+                    callback(interrupting);
+
+                }
+
+            }
+
+        }
+
+    }
+
+    fn write_c(&mut self, value: u8){
+
+        self.latches[2] = value;
+
+        let outputs = self.c_output_bits();
+
+        self.peripheral.write(2, value & outputs);
+
+    }
+
+    // Bit set/reset sets the interrupt enables of strobed ports, other bits of port C as it would be written
+    fn set_bit(&mut self, bit: u8, set: bool){
+
+        let mask = 1 << bit;
+
+        for group in 0..2{
+
+            if self.handshake_bits(group) & mask == 0{
+
+                continue;
+
+            }
+
+            if mask == INPUT_ENABLE[group] && self.strobed_input(group){
+
+                self.input_enable[group] = set;
+
+            }
+
+            if mask == OUTPUT_ENABLE[group] && self.strobed_output(group){
+
+                self.output_enable[group] = set;
+
+            }
+
+            return;
+
+        }
+
+        let value = if set { self.latches[2] | mask } else { self.latches[2] & !mask };
+
+        self.write_c(value);
+
+    }
+
+    fn write_control(&mut self, value: u8){
+
+        if value & 0x80 == 0{
+
+            self.set_bit((value >> 1) & 0x07, value & 0x01 != 0);
+
+            return;
+
+        }
+
+        // A mode word clears the output latches and the handshakes
+        self.control = value;
+
+        self.latches = [0; 3];
+
+        self.input_full = [false; 2];
+
+        self.input_enable = [false; 2];
+
+        self.output_enable = [false; 2];
+
+    }
+
+}
+
+impl Device for Ppi{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        self.poll();
+
+        let value = match port as usize {
+
+            2 => self.read_c(),
+
+            3 => 0xFF, // The control word can't be read back
+
+            port if self.strobed_input(port) => {
+
+                self.input_full[port] = false;
+
+                self.inputs[port]
+
+            },
+
+            port if self.is_input(port) => self.peripheral.read(port),
+
+            port => self.latches[port],
+
+        };
+
+        self.poll();
+
+        self.update_interrupts();
+
+        value
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        match port as usize {
+
+            2 => self.write_c(value),
+
+            3 => self.write_control(value),
+
+            port => {
+
+                self.latches[port] = value;
+
+                if !self.is_input(port) || self.mode(port) == 2{
+
+                    self.peripheral.write(port, value);
+
+                }
+
+            },
+
+        }
+
+        self.update_interrupts();
+
+    }
+
+    fn tick(&mut self, _cycles: u32){
+
+        self.poll();
+
+        self.update_interrupts();
+
+    }
+
+    fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        (0..2).filter(|group| self.interrupting[*group]).find_map(|group| self.interrupts[group]).map(Acknowledge::Restart)
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Board{
+        written: Vec<(usize, u8)>,
+        keys: VecDeque<u8>, // Strobed into port A
+        switches: u8, // On port B and the lower half of port C
+    }
+
+    impl Peripheral for Rc<RefCell<Board>>{
+
+        fn read(&mut self, _port: usize) -> u8{
+
+            self.borrow().switches
+
+        }
+
+        fn write(&mut self, port: usize, value: u8){
+
+            self.borrow_mut().written.push((port, value));
+
+        }
+
+        fn strobe(&mut self, _port: usize) -> Option<u8>{
+
+            self.borrow_mut().keys.pop_front()
+
+        }
+
+    }
+
+    #[test]
+    fn basic_and_bit_set_reset(){
+
+        let board = Rc::new(RefCell::new(Board{ switches: 0x5A, ..Board::default() }));
+
+        let mut ppi = Ppi::new(Box::new(Rc::clone(&board)));
+
+        let mut memory = [];
+
+        ppi.output(3, 0x83, &mut memory); // Mode 0: A and upper C outputs, B and lower C inputs
+
+        ppi.output(0, 0x12, &mut memory);
+
+        ppi.output(3, 0x0F, &mut memory); // Set C7
+
+        ppi.output(3, 0x01, &mut memory); // Set C0, an input, which only changes the latch
+
+        assert_eq!(ppi.input(1), 0x5A);
+
+        assert_eq!(ppi.input(2), 0x8A);
+
+        assert_eq!(board.borrow().written, vec![(0, 0x12), (2, 0x80), (2, 0x80)]);
+
+    }
+
+    #[test]
+    fn strobed_input_interrupts(){
+
+        let board = Rc::new(RefCell::new(Board::default()));
+
+        board.borrow_mut().keys.extend(b"ok".iter());
+
+        let mut ppi = Ppi::new(Box::new(Rc::clone(&board)));
+
+        ppi.interrupts[0] = Some(5);
+
+        let changes = Rc::new(RefCell::new(vec![]));
+
+        let seen = Rc::clone(&changes);
+
+        ppi.on_interrupt[0] = Some(Box::new(move |level| seen.borrow_mut().push(level)));
+
+        let mut memory = [];
+
+        ppi.output(3, 0xB0, &mut memory); // Port A strobed input in mode 1
+
+        ppi.tick(4);
+
+        assert_eq!(ppi.acknowledge(), None); // Until INTE A is set
+
+        ppi.output(3, 0x09, &mut memory);
+
+        assert_eq!(ppi.input(2) & 0x38, 0x38); // INTR, IBF and INTE
+
+        assert_eq!(ppi.acknowledge(), Some(Acknowledge::Restart(5)));
+
+        assert_eq!(ppi.input(0), b'o');
+
+        assert_eq!(ppi.input(0), b'k'); // Strobed in as soon as the buffer was read
+
+        assert_eq!(ppi.input(2) & 0x38, 0x10);
+
+        assert_eq!(*changes.borrow(), vec![true, false]);
+
+        ppi.output(3, 0xC0, &mut memory); // Mode 2, output is taken at once
+
+        ppi.output(0, 0x33, &mut memory);
+
+        assert_eq!(ppi.input(2) & 0xF8, 0x80);
+
+        assert_eq!(board.borrow().written.last(), Some(&(0, 0x33)));
+
+    }
+
+}
@@ -0,0 +1,367 @@
+// The Intel 8251 USART, with its data register on the first port of its pair and its mode, command and status registers on the second
+// Characters go to and from a console, so a program talks to the terminal or a TCP client; they move as soon as the program allows,
+// so the baud rate set on the board doesn't matter and no errors occur
+use crate::cpu::io_map::{Acknowledge, Device};
+use crate::cpm::Console;
+
+// Command word bits
+const TRANSMIT_ENABLE: u8 = 0x01;
+const RECEIVE_ENABLE: u8 = 0x04;
+const ERROR_RESET: u8 = 0x10;
+const INTERNAL_RESET: u8 = 0x40;
+
+// Status bits
+const TRANSMITTER_READY: u8 = 0x01;
+const RECEIVER_READY: u8 = 0x02;
+const TRANSMITTER_EMPTY: u8 = 0x04;
+const ERRORS: u8 = 0x38; // Parity, overrun and framing
+const DATA_SET_READY: u8 = 0x80;
+
+// What the next write to the control port is
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expecting{
+    Mode,
+    SyncCharacters(u8), // How many are left, in synchronous mode
+    Command,
+}
+
+pub struct Usart{
+    console: Console,
+    expecting: Expecting,
+    mode: u8,
+    command: u8,
+    status: u8, // Only the error bits are kept, the others follow the buffers
+    transmit: Option<u8>, // Written and not yet sent, while the transmitter is disabled
+    received: Option<u8>,
+    pub receive_interrupt: Option<u8>, // RST raised while RxRDY is set, if the pin is wired to interrupt the CPU
+    pub transmit_interrupt: Option<u8>, // RST raised while TxRDY is set
+}
+
+impl Usart{
+
+    pub fn new(console: Console) -> Self{
+
+        Usart{
+            console,
+            expecting: Expecting::Mode,
+            mode: 0,
+            command: 0,
+            status: 0,
+            transmit: None,
+            received: None,
+            receive_interrupt: None,
+            transmit_interrupt: None,
+        }
+
+    }
+
+    // Characters are 5 to 8 bits long
+    fn character_mask(&self) -> u8{
+
+        0xFF >> (3 - ((self.mode >> 2) & 0x03))
+
+    }
+
+    // Sends a character written while the transmitter was disabled once it's enabled
+    fn send(&mut self){
+
+        if self.command & TRANSMIT_ENABLE != 0{
+
+            if let Some(byte) = self.transmit.take(){
+
+                let byte = byte & self.character_mask();
+
+                self.console.write(byte);
+
+            }
+
+        }
+
+    }
+
+    fn receive(&mut self){
+
+        if self.command & RECEIVE_ENABLE != 0 && self.received.is_none(){
+
+            self.received = self.console.poll().map(|byte| byte & self.character_mask());
+
+        }
+
+    }
+
+    fn read_status(&mut self) -> u8{
+
+        self.receive();
+
+        let mut status = self.status | DATA_SET_READY;
+
+        if self.transmit.is_none(){
+
+            status |= TRANSMITTER_READY | TRANSMITTER_EMPTY;
+
+        }
+
+        if self.received.is_some(){
+
+            status |= RECEIVER_READY;
+
+        }
+
+        status
+
+    }
+
+    fn write_control(&mut self, value: u8){
+
+        match self.expecting {
+
+            Expecting::Mode => {
+
+                self.mode = value;
+
+                self.expecting = match value & 0x03 {
+                    0 => Expecting::SyncCharacters(if value & 0x80 != 0 { 1 } else { 2 }), // Synchronous, with one or two sync characters
+                    _ => Expecting::Command,
+                };
+
+            },
+
+            Expecting::SyncCharacters(1) => self.expecting = Expecting::Command,
+
+            Expecting::SyncCharacters(left) => self.expecting = Expecting::SyncCharacters(left - 1),
+
+            Expecting::Command if value & INTERNAL_RESET != 0 => self.expecting = Expecting::Mode,
+
+            Expecting::Command => {
+
+                self.command = value;
+
+                if value & ERROR_RESET != 0{
+
+                    self.status &= !ERRORS;
+
+                }
+
+                self.send();
+
+            },
+
+        }
+
+    }
+
+}
+
+impl Device for Usart{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        match port {
+
+            0 => {
+
+                self.receive();
+
+                self.received.take().unwrap_or(0)
+
+            },
+
+            _ => self.read_status(),
+
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        match port {
+
+            0 => {
+
+                self.transmit = Some(value);
+
+                self.send();
+
+            },
+
+            _ => self.write_control(value),
+
+        }
+
+    }
+
+    // The RESET input, after which the mode has to be written again and any sync characters still expected are forgotten
+    fn reset(&mut self, _memory: &mut [u8]){
+
+        self.expecting = Expecting::Mode;
+
+        self.mode = 0;
+
+        self.command = 0;
+
+        self.status = 0;
+
+        self.transmit = None;
+
+        self.received = None;
+
+    }
+
+    // The RxRDY and TxRDY pins follow the status bits while the receiver and transmitter are enabled
+    fn acknowledge(&mut self) -> Option<Acknowledge>{
+
+        self.receive();
+
+        if self.received.is_some(){
+
+            if let Some(restart) = self.receive_interrupt{
+
+                return Some(Acknowledge::Restart(restart));
+
+            }
+
+        }
+
+        if self.transmit.is_none() && self.command & TRANSMIT_ENABLE != 0{
+
+            return self.transmit_interrupt.map(Acknowledge::Restart);
+
+        }
+
+        None
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpm::Capture;
+    use crate::cpu::testing::run_program;
+
+    #[test]
+    fn echoes_with_receive_interrupts(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H
+                    XRA A           ; Put the USART into a known state, then reset it
+                    OUT 0EDH
+                    OUT 0EDH
+                    OUT 0EDH
+                    MVI A,40H
+                    OUT 0EDH
+                    MVI A,4EH       ; Asynchronous, 8 data bits, 1 stop bit, 16 times the baud rate
+                    OUT 0EDH
+                    MVI A,01H       ; Transmitter only
+                    OUT 0EDH
+                    IN 0EDH
+                    ANI 07H
+                    ADI '0'
+                    OUT 0ECH
+                    MVI A,35H       ; Receiver too, and reset errors
+                    OUT 0EDH
+                    EI
+            WAIT:   JMP WAIT
+                    ORG 0038H       ; RST 7, for a character received
+                    IN 0ECH
+                    CPI 'a'
+                    JC UPPER
+                    SUI 20H
+            UPPER:  OUT 0ECH
+                    EI
+                    RET
+        ";
+
+        let (console, sender, output) = Capture::console();
+
+        for byte in b"hi!"{
+
+            sender.send(*byte).expect("Failed to send input");
+
+        }
+
+        let mut usart = Usart::new(console);
+
+        usart.receive_interrupt = Some(7);
+
+        run_program(source, vec![(0xEC..=0xED, Box::new(usart))], 200);
+
+        assert_eq!(output.text(), "5HI!");
+
+    }
+
+    #[test]
+    fn returns_before_the_next_receive_interrupt(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H
+                    MVI A,4EH       ; Asynchronous, 8 data bits, 1 stop bit, 16 times the baud rate
+                    OUT 0EDH
+                    MVI A,05H       ; Transmitter and receiver
+                    OUT 0EDH
+                    EI
+            WAIT:   JMP WAIT
+                    ORG 0038H       ; RST 7, with the next character already waiting
+                    LXI H,0
+                    DAD SP
+                    SHLD 0200H      ; Where the stack was in the last interrupt
+                    IN 0ECH
+                    OUT 0ECH
+                    EI              ; Only takes effect after the RET
+                    RET
+        ";
+
+        let (console, sender, output) = Capture::console();
+
+        for byte in b"ab"{
+
+            sender.send(*byte).expect("Failed to send input");
+
+        }
+
+        let mut usart = Usart::new(console);
+
+        usart.receive_interrupt = Some(7);
+
+        let processor = run_program(source, vec![(0xEC..=0xED, Box::new(usart))], 50);
+
+        assert_eq!(output.text(), "ab");
+
+        assert_eq!(&processor.memory[0x0200..0x0202], &[0xFE, 0x00]); // The second interrupt wasn't taken inside the first
+
+        assert_eq!(processor.registers().stack_pointer, 0x0100);
+
+    }
+
+    #[test]
+    fn reset_then_new_mode(){
+
+        let (console, _, output) = Capture::console();
+
+        let mut usart = Usart::new(console);
+
+        usart.output(1, 0x0C, &mut []); // Synchronous, 8 data bits, two sync characters
+
+        usart.output(1, 0x16, &mut []); // Only the first sync character
+
+        usart.reset(&mut []);
+
+        assert_eq!((usart.mode, usart.expecting), (0, Expecting::Mode));
+
+        usart.output(1, 0x42, &mut []); // Asynchronous, 5 data bits, rather than the second sync character
+
+        usart.output(1, 0x01, &mut []); // Transmitter only
+
+        usart.output(0, 0xFF, &mut []);
+
+        assert_eq!(output.text(), "\u{1f}");
+
+        assert_eq!(usart.input(1) & TRANSMITTER_READY, TRANSMITTER_READY);
+
+    }
+
+}
@@ -0,0 +1,100 @@
+// Runs CPU test programs written for CP/M through the BDOS emulation, checking what they print to the console
+// A program has finished once it jumps to 0000H (warm boot) or calls BDOS function 0
+// The exercisers are not distributed with the emulator, so their tests are ignored by default:
+// copy 8080PRE.COM, TST8080.COM, CPUTEST.COM and 8080EXM.COM into tests/cpm (or the directory in CPM_TESTS_DIR)
+// and run cargo test --release -- --ignored
+use crate::cpm::{Capture, Console, Cpm, Exit};
+use crate::cpu::history::History;
+
+use std::sync::mpsc;
+
+pub struct Run{
+    pub output: String, // Everything written to the console
+    pub finished: bool, // False if the cycle limit was reached first
+    pub cycles: u64,
+}
+
+pub fn run(program: &[u8], cycle_limit: u64) -> Run{
+
+    let (_, input) = mpsc::channel(); // The exercisers only print
+
+    let output = Capture::default();
+
+    let mut cpm = Cpm::new(program, &[], std::env::temp_dir(), Console::new(input, Box::new(output.clone()), true)).expect("Failed to load program");
+
+    cpm.processor.history = History::new(0); // Not needed and slows down the longer exercisers
+
+    let exit = cpm.run(Some(cycle_limit));
+
+    Run{ output: output.text(), finished: exit == Exit::WarmBoot, cycles: cpm.processor.total_cycles }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+
+    // Runs an exerciser from the test directory and checks it finished and printed the pass message and none of the failure messages
+    fn exercise(file_name: &str, cycle_limit: u64, pass: &str, failures: &[&str]){
+
+        let directory = std::env::var("CPM_TESTS_DIR").unwrap_or_else(|_| "tests/cpm".to_string());
+
+        let path = std::path::Path::new(&directory).join(file_name);
+
+        let program = std::fs::read(&path).unwrap_or_else(|_| panic!("Failed to read {}, see the top of src/exerciser.rs", path.display()));
+
+        check(file_name, &run(&program, cycle_limit), pass, failures);
+
+    }
+
+    fn check(name: &str, run: &Run, pass: &str, failures: &[&str]){
+
+        assert!(run.finished, "{} did not finish within {} cycles, output:\n{}", name, run.cycles, run.output);
+
+        assert!(run.output.contains(pass) && !failures.iter().any(|failure| run.output.contains(failure)), "{} failed, output:\n{}", name, run.output);
+
+    }
+
+    #[test]
+    fn cpudiag(){
+
+        let program = std::fs::read("cpudiag.bin").expect("Failed to read cpudiag.bin");
+
+        check("cpudiag.bin", &run(&program, 10_000_000), "CPU IS OPERATIONAL", &["FAILED"]);
+
+    }
+
+    #[test]
+    #[ignore = "needs 8080PRE.COM in tests/cpm"]
+    fn preliminary(){
+
+        exercise("8080PRE.COM", 10_000_000, "8080 Preliminary tests complete", &["ERROR", "Error"]);
+
+    }
+
+    #[test]
+    #[ignore = "needs TST8080.COM in tests/cpm"]
+    fn tst8080(){
+
+        exercise("TST8080.COM", 10_000_000, "CPU IS OPERATIONAL", &["FAILED"]);
+
+    }
+
+    #[test]
+    #[ignore = "needs CPUTEST.COM in tests/cpm"]
+    fn cputest(){
+
+        exercise("CPUTEST.COM", 1_000_000_000, "CPU TESTS OK", &["FAILED", "ERROR"]);
+
+    }
+
+    #[test]
+    #[ignore = "needs 8080EXM.COM in tests/cpm, takes several minutes"]
+    fn instruction_exerciser(){
+
+        exercise("8080EXM.COM", 50_000_000_000, "Tests complete", &["ERROR"]);
+
+    }
+
+}
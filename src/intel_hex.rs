@@ -0,0 +1,229 @@
+use std::io::{self, Write};
+
+const BYTES_PER_RECORD: usize = 16;
+
+// A run of bytes stored at consecutive addresses
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment{
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+pub fn load(file_name: &str) -> Result<Vec<Segment>, String>{
+
+    let text = std::fs::read_to_string(file_name).map_err(|_| format!("Unable to read '{}'", file_name))?;
+
+    parse(&text).map_err(|error| format!("{}: {}", file_name, error))
+
+}
+
+// Reads data records into segments, merging records that follow on from each other
+// Extended segment and linear address records are accepted as long as the data stays within 64KiB
+pub fn parse(text: &str) -> Result<Vec<Segment>, String>{
+
+    let mut segments: Vec<Segment> = vec![];
+
+    let mut base_address: u32 = 0;
+
+    for (line_index, line) in text.lines().enumerate(){
+
+        let line = line.trim();
+
+        if line.is_empty(){
+
+            continue;
+
+        }
+
+        let error = |message: &str| format!("line {}: {}", line_index + 1, message);
+
+        let digits = line.strip_prefix(':').ok_or_else(|| error("Record does not start with ':'"))?;
+
+        if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()){
+
+            return Err(error("Record contains a character that is not hexadecimal"));
+
+        }
+
+        if digits.len() % 2 != 0 || digits.len() < 10{
+
+            return Err(error("Record is too short"));
+
+        }
+
+        let record: Vec<u8> = (0..digits.len()).step_by(2)
+            .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).expect("Failed to parse hexadecimal digits"))
+            .collect();
+
+        let length = record[0] as usize;
+
+        if record.len() != length + 5{
+
+            return Err(error("Record length does not match its data"));
+
+        }
+
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0{
+
+            return Err(error("Checksum mismatch"));
+
+        }
+
+        let address = ((record[1] as u32) << 8) | record[2] as u32;
+
+        let data = &record[4..4 + length];
+
+        match record[3] {
+
+            0x00 => {
+
+                let address = base_address + address;
+
+                if address + length as u32 > 0x10000{
+
+                    return Err(error("Data is outside the 64KiB address space"));
+
+                }
+
+                match segments.last_mut() {
+
+                    Some(segment) if segment.address as u32 + segment.bytes.len() as u32 == address => segment.bytes.extend_from_slice(data),
+
+                    _ => if !data.is_empty(){
+                        segments.push(Segment{
+                            address: address as u16,
+                            bytes: data.to_vec(),
+                        });
+                    },
+
+                }
+
+            },
+
+            0x01 => return Ok(segments),
+
+            0x02 | 0x04 if length == 2 => {
+
+                let value = ((data[0] as u32) << 8) | data[1] as u32;
+
+                base_address = if record[3] == 0x02 { value << 4 } else { value << 16 };
+
+            },
+
+            0x03 | 0x05 => {}, // Start address, execution always begins where the machine says
+
+            _ => return Err(error(&format!("Unsupported record type {:02X}", record[3]))),
+
+        }
+
+    }
+
+    Err("Missing end of file record".to_string())
+
+}
+
+// Writes the segments as data records followed by an end of file record
+pub fn write(segments: &[Segment], writer: &mut dyn Write) -> io::Result<()>{
+
+    for segment in segments{
+
+        for (index, chunk) in segment.bytes.chunks(BYTES_PER_RECORD).enumerate(){
+
+            write_record(writer, segment.address.wrapping_add((index * BYTES_PER_RECORD) as u16), 0x00, chunk)?;
+
+        }
+
+    }
+
+    write_record(writer, 0, 0x01, &[])
+
+}
+
+// Format: :<length><address><type><data><checksum>, the checksum makes the sum of all bytes 0
+fn write_record(writer: &mut dyn Write, address: u16, record_type: u8, data: &[u8]) -> io::Result<()>{
+
+    let mut record = vec![data.len() as u8, (address >> 8) as u8, address as u8, record_type];
+
+    record.extend_from_slice(data);
+
+    let checksum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+
+    record.push(checksum);
+
+    let text: String = record.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+    writeln!(writer, ":{}", text)
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpu::{FileToLoad, Processor8080};
+
+    #[test]
+    fn written_segments_parse_back(){
+
+        let segments = vec![
+            Segment{ address: 0x0100, bytes: (0..40).collect() },
+            Segment{ address: 0xFFF0, bytes: vec![0xAA; 16] },
+        ];
+
+        let mut output = vec![];
+
+        write(&segments, &mut output).expect("Failed to write records");
+
+        assert_eq!(parse(&String::from_utf8(output).expect("Records are not UTF-8")), Ok(segments));
+
+    }
+
+    #[test]
+    fn extended_addresses_and_bad_records(){
+
+        let text = ":020000020010EC\n:020000000102FB\n:00000001FF\n"; // Segment 0010H, so data at 0000H loads at 0100H
+
+        assert_eq!(parse(text), Ok(vec![Segment{ address: 0x0100, bytes: vec![0x01, 0x02] }]));
+
+        assert_eq!(parse(":0200000001022C\n:00000001FF"), Err("line 1: Checksum mismatch".to_string()));
+
+        assert_eq!(parse(":020000040001F9\n:0100000000FF\n:00000001FF"), Err("line 2: Data is outside the 64KiB address space".to_string()));
+
+        assert_eq!(parse(":0100000000FF"), Err("Missing end of file record".to_string()));
+
+    }
+
+    #[test]
+    fn only_records_are_read_only(){
+
+        let segments = vec![
+            Segment{ address: 0x0100, bytes: vec![0x11; 4] },
+            Segment{ address: 0x0200, bytes: vec![0x22; 4] },
+        ];
+
+        let name = std::env::temp_dir().join(format!("emulator_8080_rom_{}.hex", std::process::id()));
+
+        let mut file = std::fs::File::create(&name).expect("Failed to create file");
+
+        write(&segments, &mut file).expect("Failed to write records");
+
+        let mut processor = Processor8080::new(|_, _, _| 0, |_, _, _, _, _| {}, false);
+
+        processor.initialize(vec![FileToLoad{
+            name: name.to_str().expect("Invalid path").to_string(),
+            offset: 0x1000,
+            size: 0,
+            read_only: true,
+        }], 0x10000);
+
+        std::fs::remove_file(&name).expect("Failed to remove file");
+
+        assert_eq!((processor.examine(0x1100), processor.examine(0x1203)), (0x11, 0x22));
+
+        let read_only: Vec<u16> = [0x10FF, 0x1100, 0x1103, 0x1104, 0x11FF, 0x1200, 0x1203, 0x1204].iter().copied().filter(|address| processor.is_read_only(*address)).collect();
+
+        assert_eq!(read_only, vec![0x1100, 0x1103, 0x1200, 0x1203]); // Not the gap between the records
+
+    }
+
+}
@@ -1,10 +1,28 @@
 extern crate sdl2;
 
 mod space_invaders;
+mod cpm_machine;
+mod altair;
+mod sdk80;
+mod sbc_80_20;
+mod front_panel;
 mod test;
+mod debugger;
 
 use crate::cpu::*;
-
+use crate::cpu::watchpoint::Watchpoint;
+use crate::cpu::disassembler::symbols::SymbolTable;
+use crate::cpu::trace::Trace;
+use crate::cpu::history::History;
+use crate::cpu::profiler::Profiler;
+use crate::cpu::coverage::Coverage;
+use crate::cpu::io_map::IoMap;
+use front_panel::FrontPanel;
+
+use crate::cpm::Console;
+
+use std::net::TcpListener;
+use std::ops::Range;
 use std::time::SystemTime;
 
 use sdl2::mixer;
@@ -18,12 +36,31 @@ pub struct Machine{
     key_event_handler: fn(&mut Machine),
     drawer: fn(&mut Machine),
     timestamp: SystemTime,
+    pub running: bool, // Cleared while the CPU is stopped from a front panel
+    front_panel: Option<FrontPanel>,
 
     pub canvas: sdl2::render::Canvas<sdl2::video::Window>,
     sdl_context: sdl2::Sdl,
     pub audio_controller: AudioController,
 }
 
+#[derive(Default)]
+pub struct Options{
+    pub log_to_file: bool,
+    pub test: bool,
+    pub watchpoints: Vec<Watchpoint>,
+    pub symbols: SymbolTable,
+    pub trace: Option<Trace>,
+    pub history_size: Option<usize>, // Number of instructions kept for crash reports, the default is used if not set
+    pub profile: Option<String>, // File the profile report is written to
+    pub coverage: Option<String>, // File the coverage map is written to
+    pub disks: Vec<String>, // Disk images for drives A, B, ... of machines with disk drives
+    pub files: Vec<FileToLoad>, // Loaded after the machine's own files
+    pub ram_size: Option<usize>, // Bytes of RAM fitted, for machines where it can be changed
+    pub start_address: Option<u16>, // Where execution starts instead of the machine's usual address
+    pub serial_port: Option<u16>, // Local TCP port the serial console is served on instead of the terminal
+}
+
 pub struct SetupConfiguration{
     input_handler: fn(&mut Processor8080, u8, &Vec<u8>) -> u8,
     output_handler: fn(&mut Processor8080, u8, u8, &mut Vec<u8>, &mut AudioController),
@@ -32,15 +69,55 @@ pub struct SetupConfiguration{
     drawer: fn(&mut Machine),
     files: Vec<FileToLoad>,
     ports: Vec<u8>,
+    memory_size: usize, // Memory is filled with zeros up to this size after the files are loaded
+    mirror_ram: bool,
+    io: IoMap,
+    disks: Vec<String>,
+    ram_size: Option<usize>,
+    read_only: Vec<Range<usize>>, // Writes to these addresses are ignored, as well as to the read only files loaded
+    front_panel: Option<FrontPanel>,
+    serial_port: Option<u16>,
 
     window: sdl2::video::Window,
 
     audio_tracks: Vec<Chunk>,
 }
 
+impl SetupConfiguration{
+
+    // The console for machines with a serial terminal, which fails if the port can't be listened on
+    fn console(&self) -> Result<Console, String>{
+
+        match self.serial_port {
+
+            Some(port) => {
+
+                let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|error| format!("Failed to listen on port {}: {}", port, error))?;
+
+                println!("The serial console is on port {}, connect with e.g. telnet localhost {}", port, port);
+
+                Ok(Console::listen(listener))
+
+            },
+
+            None => {
+
+                println!("The serial console is this terminal, type into it while the emulator window has focus or not");
+
+                Ok(Console::standard())
+
+            },
+
+        }
+
+    }
+
+}
+
 impl Machine{
 
-    pub fn new(game_id: u8, log_to_file: bool, test: bool) -> Machine{
+    // Fails when the machine can't be set up from the options, e.g. when a disk image is missing
+    pub fn new(game_id: u8, options: Options) -> Result<Machine, String>{
         
         let sdl_context = sdl2::init().expect("Failed to initialize SDL2");
         let video_subsystem = sdl_context.video().expect("Failed to retrieve SDL2 video subsystem");
@@ -63,13 +140,21 @@ impl Machine{
             drawer: test::draw,
             files: vec![],
             ports: vec![0u8; 256],
+            memory_size: 0x4000,
+            mirror_ram: false,
+            io: IoMap::default(),
+            disks: options.disks,
+            ram_size: options.ram_size,
+            read_only: vec![],
+            front_panel: None,
+            serial_port: options.serial_port,
 
             window: window,
 
             audio_tracks: vec![],
         };
 
-        if !test{
+        if !options.test{
 
             match game_id {
     
@@ -78,19 +163,47 @@ impl Machine{
                     space_invaders::setup(&mut setup_config);
     
                 }, // Space Invaders
+
+                1 => {
+
+                    cpm_machine::setup(&mut setup_config)?;
+
+                }, // CP/M 2.2
+
+                2 => {
+
+                    altair::setup(&mut setup_config)?;
+
+                }, // Altair 8800
+
+                3 => {
+
+                    sdk80::setup(&mut setup_config)?;
+
+                }, // Intel SDK-80
+
+                4 => {
+
+                    sbc_80_20::setup(&mut setup_config)?;
+
+                }, // Intel iSBC 80/20
     
                 _ => panic!("Game ID is invalid"),
             }
 
         }
+
+        setup_config.files.extend(options.files);
     
         let mut new_arcade = Machine{
-            cpu: Processor8080::new(setup_config.input_handler, setup_config.output_handler, log_to_file),
+            cpu: Processor8080::new(setup_config.input_handler, setup_config.output_handler, options.log_to_file),
             ports: setup_config.ports,
             key_event_handler: setup_config.key_event_handler,
             interrupt_handler: setup_config.interrupt_handler,
             drawer: setup_config.drawer,
             timestamp: SystemTime::now(),
+            running: true,
+            front_panel: setup_config.front_panel,
 
             canvas: setup_config.window.into_canvas().build().expect("Failed to create canvas"),
             sdl_context: sdl_context,
@@ -98,18 +211,46 @@ impl Machine{
             audio_controller: AudioController::new(setup_config.audio_tracks),
         };
     
-        if test{
+        new_arcade.cpu.mirror_ram = setup_config.mirror_ram;
+
+        new_arcade.cpu.io = setup_config.io;
+
+        new_arcade.cpu.watchpoints = options.watchpoints;
+
+        new_arcade.cpu.formatter.symbols = options.symbols;
+
+        new_arcade.cpu.trace = options.trace;
+
+        if let Some(size) = options.history_size{
+
+            new_arcade.cpu.history = History::new(size);
+
+        }
+
+        new_arcade.cpu.profiler = options.profile.as_deref().map(Profiler::new);
+
+        new_arcade.cpu.coverage = options.coverage.as_deref().map(Coverage::new); // Set before loading so each file is summarised
+
+        if options.test{
     
             new_arcade.cpu.test();
     
         }
         else {
     
-            new_arcade.cpu.initialize(setup_config.files);
+            new_arcade.cpu.initialize(setup_config.files, setup_config.memory_size);
+
+            new_arcade.cpu.read_only.extend(setup_config.read_only);
     
         }
+
+        if let Some(address) = options.start_address{
+
+            new_arcade.cpu.jump(address);
+
+        }
     
-        new_arcade
+        Ok(new_arcade)
     
     }
 
@@ -127,13 +268,19 @@ impl Machine{
 
                 self.timestamp = self.get_time();
 
-                while cycle <= (2_000_000 / 60) as u16{ // The number of cycles that should execute per frame
+                while self.running && cycle <= (2_000_000 / 60) as u16{ // The number of cycles that should execute per frame
 
                     saved_cycle_count = self.cpu.cycles_elapsed;
 
                     self.cpu.emulate(&mut self.ports, &mut self.audio_controller);
 
-                    cycle += self.cpu.cycles_elapsed - saved_cycle_count;
+                    cycle += self.cpu.cycles_elapsed - saved_cycle_count; // Devices were ticked by emulate
+
+                    if let Some(reason) = self.cpu.take_break(){
+
+                        debugger::enter(self, &reason);
+
+                    }
         
                     (self.interrupt_handler)(self); // Handle any program-specific interrupts
     
@@ -143,6 +290,12 @@ impl Machine{
 
                 (self.drawer)(self); // Draw the window
 
+                if let Some(profiler) = &mut self.cpu.profiler{
+
+                    profiler.frames += 1;
+
+                }
+
             }
 
         }
@@ -0,0 +1,195 @@
+// The MITS Altair 8800, with RAM from 1 to 64KiB and the terminal connected to both an 88-SIO and an 88-2SIO serial board
+// so that programs built for either find it: the 88-SIO on ports 00H (status) and 01H (data), the first port of the 88-2SIO,
+// a Motorola 6850 ACIA, on ports 10H (status and control) and 11H (data)
+// Programs such as Altair BASIC are loaded into RAM with --load and monitor ROMs with --rom
+// The window is the front panel, whose upper eight address switches are the sense switches read from port FFH
+use crate::machine::*;
+use crate::machine::front_panel::{self, FrontPanel, SenseSwitches};
+use crate::cpu::io_map::{Device, IoMap};
+use crate::cpm::Console;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Fails if the console's port can't be listened on
+pub fn setup(setup_config: &mut SetupConfiguration) -> Result<(), String>{
+
+    println!("\n\n\n\
+        Machine Interaction:\n\n\t\
+            Escape Key  -> Close Emulator\n\t\
+            F9 Key      -> Break Into Debugger\n\t\
+            P Key       -> Write Profile\n\n\
+        Front Panel:\n\n\t\
+            0-9, A-F    -> Shift A Hexadecimal Digit Into The Address And Data Switches\n\t\
+            Backspace   -> Clear The Address And Data Switches\n\t\
+            F1 - F8     -> Stop, Run, Single Step, Examine, Examine Next, Deposit, Deposit Next, Reset\n\t\
+            Mouse Click -> Flip The Switch Clicked\n\n\
+        Load programs into RAM with --load <file>[@<address>], ROMs with\n\
+        --rom <file>[@<address>], choose the RAM fitted with --ram <KiB> and where to start with --start <address>\n\
+    ");
+
+    setup_config.key_event_handler = front_panel::key_event;
+
+    setup_config.interrupt_handler = test::test_interrupt; // Nothing interrupts the CPU
+
+    setup_config.drawer = front_panel::draw;
+
+    setup_config.memory_size = 0x10000;
+
+    let ram_size = setup_config.ram_size.unwrap_or(0x10000);
+
+    if ram_size < 0x10000{
+
+        setup_config.read_only.push(ram_size..0x10000); // No memory is fitted there, so nothing written is kept
+
+    }
+
+    let panel = FrontPanel::default();
+
+    let console = setup_config.console()?;
+
+    attach_devices(&mut setup_config.io, console, panel.sense_switches());
+
+    setup_config.front_panel = Some(panel);
+
+    setup_config.window.set_title("Altair 8800").expect("Failed to set window title");
+
+    setup_config.window.set_size(front_panel::WIDTH, front_panel::HEIGHT).expect("Failed to size window");
+
+    Ok(())
+
+}
+
+fn attach_devices(io: &mut IoMap, console: Console, sense_switches: SenseSwitches){
+
+    let console = Rc::new(RefCell::new(console));
+
+    io.attach(0x00..=0x01, Box::new(Sio{ console: Rc::clone(&console) }));
+
+    io.attach(0x10..=0x11, Box::new(Acia{ console }));
+
+    io.attach(0xFF..=0xFF, Box::new(sense_switches));
+
+}
+
+// Status bits are active low: bit 0 is clear when a character has been received and bit 7 when one can be sent
+struct Sio{
+    console: Rc<RefCell<Console>>,
+}
+
+impl Device for Sio{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        let mut console = self.console.borrow_mut();
+
+        match port {
+            0 => if console.status() { 0x00 } else { 0x01 },
+            _ => console.poll().unwrap_or(0),
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        if port == 1{
+
+            self.console.borrow_mut().write(value & 0x7F); // Without the parity bit
+
+        }
+
+        // Writes to the status port enable interrupts, which the board doesn't raise here
+
+    }
+
+}
+
+// Status bit 0 is set when a character has been received and bit 1 when one can be sent
+struct Acia{
+    console: Rc<RefCell<Console>>,
+}
+
+impl Device for Acia{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        let mut console = self.console.borrow_mut();
+
+        match port {
+            0 => if console.status() { 0x03 } else { 0x02 },
+            _ => console.poll().unwrap_or(0),
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        if port == 1{
+
+            self.console.borrow_mut().write(value & 0x7F);
+
+        }
+
+        // Control words set the word format and reset the ACIA, neither of which matters to the host
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::cpm::Capture;
+    use crate::cpu::testing::{load_program, run};
+
+    #[test]
+    fn serial_boards_and_ram_size(){
+
+        let source = "
+                    ORG 0
+                    LXI SP,0100H
+            WAIT:   IN 0            ; 88-SIO, bit 0 is set until a character arrives
+                    RRC
+                    JC WAIT
+                    IN 1
+                    MOV B,A
+            READY:  IN 10H          ; 88-2SIO, bit 1 is set when a character can be sent
+                    ANI 02H
+                    JZ READY
+                    MOV A,B
+                    OUT 11H
+                    IN 10H          ; Nothing more has been received
+                    ANI 01H
+                    CALL PRINT
+                    LXI H,2000H     ; Past the end of RAM
+                    MVI M,1
+                    MOV A,M
+                    CALL PRINT
+                    LXI H,0800H
+                    MVI M,1
+                    MOV A,M
+                    CALL PRINT
+            DONE:   JMP DONE
+            PRINT:  ADI '0'
+                    OUT 1
+                    RET
+        ";
+
+        let (console, sender, output) = Capture::console();
+
+        sender.send(b'x').expect("Failed to send input");
+
+        let mut processor = load_program(source, vec![]);
+
+        attach_devices(&mut processor.io, console, FrontPanel::default().sense_switches());
+
+        processor.read_only.push(0x1000..0x10000); // 4KiB of RAM
+
+        run(&mut processor, 100);
+
+        assert_eq!(output.text(), "x001");
+
+    }
+
+}
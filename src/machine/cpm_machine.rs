@@ -0,0 +1,337 @@
+// A CP/M 2.2 computer in the style of the z80pack simulator, booted from IBM 3740 8" single density disk images
+// The console is on ports 0 (status) and 1 (data) and the floppy disk controller on ports 10 to 17
+// On reset the controller reads the first sector of drive A into 0000H, where the CPU starts, and the boot loader there does the rest
+use crate::machine::*;
+use crate::cpu::io_map::Device;
+use crate::cpm::Console;
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const TRACKS: u8 = 77;
+const SECTORS: u16 = 26; // Numbered from 1
+const SECTOR_SIZE: usize = 128;
+const DRIVES: usize = 4;
+
+const DEFAULT_DISK: &str = "cpm-source/drivea.dsk";
+
+// Fails if a disk image can't be opened, naming it, or the console's port can't be listened on
+pub fn setup(setup_config: &mut SetupConfiguration) -> Result<(), String>{
+
+    println!("\n\n\n\
+        Machine Interaction:\n\n\t\
+            Escape Key  -> Close Emulator\n\t\
+            B Key       -> Break Into Debugger\n\t\
+            P Key       -> Write Profile\n\
+    ");
+
+    setup_config.key_event_handler = test::key_event;
+
+    setup_config.interrupt_handler = test::test_interrupt; // Nothing interrupts the CPU
+
+    setup_config.drawer = test::draw; // Everything is written to the console
+
+    setup_config.memory_size = 0x10000;
+
+    if setup_config.disks.is_empty(){
+
+        if !std::path::Path::new(DEFAULT_DISK).exists(){
+
+            return Err(format!("Drive A defaults to '{}', which does not exist, give a disk image with --disk <image>", DEFAULT_DISK));
+
+        }
+
+        setup_config.disks.push(DEFAULT_DISK.to_string());
+
+    }
+
+    let drives = setup_config.disks.iter().take(DRIVES).map(|name| {
+        Disk::open(name).map_err(|error| format!("Failed to open disk image '{}': {}", name, error))
+    }).collect::<Result<_, _>>()?;
+
+    let console = setup_config.console()?;
+
+    setup_config.io.attach(0..=1, Box::new(ConsolePort{ console }));
+
+    setup_config.io.attach(10..=17, Box::new(FloppyController::new(drives)));
+
+    setup_config.window.set_title("CP/M 2.2").expect("Failed to set window title");
+
+    setup_config.window.set_size(256, 64).expect("Failed to size window");
+
+    Ok(())
+
+}
+
+struct ConsolePort{
+    console: Console,
+}
+
+impl Device for ConsolePort{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        match port {
+            0 => if self.console.status() { 0xFF } else { 0 },
+            _ => self.console.poll().unwrap_or(0), // Never waits, as the machine has to keep handling window events
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, _memory: &mut [u8]){
+
+        if port == 1{
+
+            self.console.write(value);
+
+        }
+
+    }
+
+}
+
+struct Disk{
+    file: File,
+    writable: bool,
+}
+
+impl Disk{
+
+    // Read only images, e.g. those without write permission, can still be booted from
+    fn open(name: &str) -> std::io::Result<Self>{
+
+        match OpenOptions::new().read(true).write(true).open(name) {
+            Ok(file) => Ok(Disk{ file, writable: true }),
+            Err(_) => Ok(Disk{ file: File::open(name)?, writable: false }),
+        }
+
+    }
+
+}
+
+// Registers, by port: 10 drive, 11 track, 12 sector, 13 command (0 read, 1 write), 14 status, 15 and 16 DMA address, 17 sector high byte
+// The status is 0 after a successful command, otherwise 1 no such drive, 2 bad track, 3 bad sector, 4 seek error, 5 read error,
+// 6 write error or 7 unknown command
+struct FloppyController{
+    drives: Vec<Disk>, // Drive A first
+    drive: u8,
+    track: u8,
+    sector: u16,
+    status: u8,
+    dma: u16, // Where sectors are read to and written from
+}
+
+impl FloppyController{
+
+    fn new(drives: Vec<Disk>) -> Self{
+
+        FloppyController{
+            drives,
+            drive: 0,
+            track: 0,
+            sector: 1,
+            status: 0,
+            dma: 0,
+        }
+
+    }
+
+    fn transfer(&mut self, command: u8, memory: &mut [u8]) -> Result<(), u8>{
+
+        let dma = self.dma;
+
+        let disk = self.drives.get_mut(self.drive as usize).ok_or(1)?;
+
+        if self.track >= TRACKS{
+
+            return Err(2);
+
+        }
+
+        if self.sector == 0 || self.sector > SECTORS{
+
+            return Err(3);
+
+        }
+
+        let offset = (self.track as u64 * SECTORS as u64 + self.sector as u64 - 1) * SECTOR_SIZE as u64;
+
+        disk.file.seek(SeekFrom::Start(offset)).map_err(|_| 4)?;
+
+        match command {
+
+            0 => {
+
+                let mut sector = [0; SECTOR_SIZE];
+
+                disk.file.read_exact(&mut sector).map_err(|_| 5)?;
+
+                for (index, byte) in sector.iter().enumerate(){
+
+                    memory[dma.wrapping_add(index as u16) as usize] = *byte;
+
+                }
+
+            },
+
+            1 => {
+
+                if !disk.writable{
+
+                    return Err(6);
+
+                }
+
+                let sector: Vec<u8> = (0..SECTOR_SIZE).map(|index| memory[dma.wrapping_add(index as u16) as usize]).collect();
+
+                disk.file.write_all(&sector).map_err(|_| 6)?;
+
+            },
+
+            _ => return Err(7),
+
+        }
+
+        Ok(())
+
+    }
+
+}
+
+impl Device for FloppyController{
+
+    fn input(&mut self, port: u8) -> u8{
+
+        match port {
+            0 => self.drive,
+            1 => self.track,
+            2 => self.sector as u8,
+            4 => self.status,
+            5 => self.dma as u8,
+            6 => (self.dma >> 8) as u8,
+            7 => (self.sector >> 8) as u8,
+            _ => 0,
+        }
+
+    }
+
+    fn output(&mut self, port: u8, value: u8, memory: &mut [u8]){
+
+        match port {
+
+            0 => self.drive = value,
+
+            1 => self.track = value,
+
+            2 => self.sector = (self.sector & 0xFF00) | value as u16,
+
+            3 => self.status = self.transfer(value, memory).err().unwrap_or(0),
+
+            5 => self.dma = (self.dma & 0xFF00) | value as u16,
+
+            6 => self.dma = (self.dma & 0x00FF) | ((value as u16) << 8),
+
+            7 => self.sector = (self.sector & 0x00FF) | ((value as u16) << 8),
+
+            _ => {},
+
+        }
+
+    }
+
+    // Boots from drive A
+    fn reset(&mut self, memory: &mut [u8]){
+
+        *self = FloppyController::new(std::mem::take(&mut self.drives));
+
+        self.status = self.transfer(0, memory).err().unwrap_or(0);
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+    use crate::asm::assemble;
+    use crate::cpm::Capture;
+    use crate::cpu::testing::{run_program, Devices};
+
+    #[test]
+    fn boots_and_transfers_sectors(){
+
+        let boot_loader = "
+                    ORG 0
+                    LXI SP,0100H
+                    MVI A,1         ; Read track 1, sector 1 to 1000H
+                    OUT 11
+                    OUT 12
+                    XRA A
+                    OUT 17
+                    OUT 15
+                    MVI A,10H
+                    OUT 16
+                    XRA A
+                    OUT 13
+                    CALL STATUS
+                    LXI H,1000H
+            PRINT:  MOV A,M
+                    CPI '$'
+                    JZ WRITE
+                    OUT 1
+                    INX H
+                    JMP PRINT
+            WRITE:  MVI A,2         ; Write it to track 2, sector 26
+                    OUT 11
+                    MVI A,26
+                    OUT 12
+                    MVI A,1
+                    OUT 13
+                    CALL STATUS
+                    MVI A,1         ; Drive B has no image
+                    OUT 10
+                    XRA A
+                    OUT 13
+                    CALL STATUS
+            DONE:   JMP DONE
+            STATUS: IN 14
+                    ADI '0'
+                    OUT 1
+                    RET
+        ";
+
+        let (_, boot_loader) = assemble(boot_loader).expect("Failed to assemble").to_binary();
+
+        let mut image = vec![0; TRACKS as usize * SECTORS as usize * SECTOR_SIZE];
+
+        image[..boot_loader.len()].copy_from_slice(&boot_loader);
+
+        image[SECTORS as usize * SECTOR_SIZE..][..6].copy_from_slice(b"hello$");
+
+        let name = std::env::temp_dir().join(format!("emulator_8080_disk_{}.dsk", std::process::id()));
+
+        std::fs::write(&name, &image).expect("Failed to write disk image");
+
+        let disk = Disk::open(name.to_str().expect("Invalid path")).expect("Failed to open disk image");
+
+        let (console, _, output) = Capture::console();
+
+        let devices: Devices = vec![
+            (0..=1, Box::new(ConsolePort{ console })),
+            (10..=17, Box::new(FloppyController::new(vec![disk]))),
+        ];
+
+        run_program("", devices, 200); // The boot loader is read from the disk on reset
+
+        let image = std::fs::read(&name).expect("Failed to read disk image");
+
+        std::fs::remove_file(&name).expect("Failed to remove disk image");
+
+        assert_eq!(output.text(), "0hello01");
+
+        assert_eq!(&image[(2 * SECTORS as usize + 25) * SECTOR_SIZE..][..6], b"hello$");
+
+    }
+
+}
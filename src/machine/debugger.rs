@@ -0,0 +1,415 @@
+use crate::machine::*;
+use crate::cpu::watchpoint::{AccessKind, WatchAction, Watchpoint};
+use crate::cpu::disassembler::{self, Syntax};
+use crate::intel_hex::{self, Segment};
+
+use std::io::{self, Write};
+
+pub fn enter(machine: &mut Machine, reason: &str){
+
+    println!("\nDebugger: {}", reason);
+
+    display_history(machine, 16);
+
+    display_registers(machine);
+
+    loop {
+
+        print!("(debug) :>> ");
+
+        io::stdout().flush().expect("Failed to flush standard output");
+
+        let mut line = String::new();
+
+        if io::stdin().read_line(&mut line).expect("Failed to read from input stream") == 0{
+
+            return; // Input closed, resume emulation
+
+        }
+
+        let mut words = line.split_whitespace();
+
+        let command = match words.next() {
+
+            Some(command) => command.to_lowercase(),
+
+            None => continue,
+
+        };
+
+        let arguments: Vec<&str> = words.collect();
+
+        match command.as_str() {
+
+            "c" | "continue" => return,
+
+            "s" | "step" => {
+
+                let count = arguments.first().and_then(|count| count.parse::<u32>().ok()).unwrap_or(1);
+
+                for _ in 0..count{
+
+                    machine.cpu.emulate(&mut machine.ports, &mut machine.audio_controller);
+
+                    if let Some(reason) = machine.cpu.take_break(){
+
+                        println!("{}", reason);
+
+                        break;
+
+                    }
+
+                }
+
+                display_registers(machine);
+
+            },
+
+            "r" | "registers" => display_registers(machine),
+
+            "m" | "memory" => display_memory(machine, &arguments),
+
+            "u" | "disassemble" => display_disassembly(machine, &arguments),
+
+            "history" => {
+
+                let count = arguments.first().and_then(|count| count.parse::<usize>().ok()).unwrap_or(64);
+
+                display_history(machine, count);
+
+            },
+
+            "bt" | "backtrace" => {
+
+                let cpu = &machine.cpu;
+
+                cpu.call_stack.write(cpu.registers().program_counter, &cpu.memory, &cpu.formatter, &mut io::stdout()).expect("Failed to write to standard output");
+
+            },
+
+            "syntax" => {
+
+                match arguments.first().map(|syntax| syntax.to_lowercase()).as_deref() {
+
+                    Some("intel") => machine.cpu.formatter.syntax = Syntax::Intel,
+
+                    Some("zilog") => machine.cpu.formatter.syntax = Syntax::Zilog,
+
+                    _ => println!("Usage: syntax <intel|zilog>"),
+
+                }
+
+            },
+
+            "save" => {
+
+                match save_memory(machine, &arguments) {
+
+                    Ok(message) => println!("{}", message),
+
+                    Err(error) => println!("{}", error),
+
+                }
+
+            },
+
+            "w" | "watch" => {
+
+                match Watchpoint::parse(&arguments.join(" "), &machine.cpu.formatter.symbols) {
+
+                    Ok(watchpoint) => add_watchpoint(machine, watchpoint),
+
+                    Err(error) => println!("{}", error),
+
+                }
+
+            },
+
+            "b" | "break" => {
+
+                match arguments.first().map(|address| machine.cpu.formatter.symbols.resolve(address)) {
+
+                    Some(Ok(address)) => add_watchpoint(machine, Watchpoint{
+                        start: address,
+                        end: address,
+                        kind: AccessKind::Execute,
+                        value: None,
+                        action: WatchAction::Break,
+                    }),
+
+                    Some(Err(error)) => println!("{}", error),
+
+                    None => println!("Usage: break <address or symbol>"),
+
+                }
+
+            },
+
+            "l" | "list" => {
+
+                for (index, watchpoint) in machine.cpu.watchpoints.iter().enumerate(){
+
+                    println!("{}: {}", index, watchpoint);
+
+                }
+
+            },
+
+            "d" | "delete" => {
+
+                match arguments.first().and_then(|index| index.parse::<usize>().ok()) {
+
+                    Some(index) if index < machine.cpu.watchpoints.len() => {
+
+                        println!("Deleted watchpoint {}: {}", index, machine.cpu.watchpoints.remove(index));
+
+                    },
+
+                    _ => println!("Usage: delete <watchpoint index>"),
+
+                }
+
+            },
+
+            "q" | "quit" => {
+
+                machine.cpu.flush_output();
+
+                machine.audio_controller.close();
+
+                std::process::exit(0);
+
+            },
+
+            "h" | "help" => display_help(),
+
+            _ => println!("Unknown command '{}', type 'help' for a list of commands", command),
+
+        }
+
+    }
+
+}
+
+fn add_watchpoint(machine: &mut Machine, watchpoint: Watchpoint){
+
+    println!("Added watchpoint {}: {}", machine.cpu.watchpoints.len(), watchpoint);
+
+    machine.cpu.watchpoints.push(watchpoint);
+
+}
+
+fn display_history(machine: &Machine, count: usize){
+
+    if machine.cpu.history.is_enabled(){
+
+        println!("Last instructions executed, oldest first:");
+
+        machine.cpu.history.write(count, &machine.cpu.formatter, &mut io::stdout()).expect("Failed to write to standard output");
+
+    }
+
+}
+
+fn display_registers(machine: &Machine){
+
+    let registers = machine.cpu.registers();
+
+    let program_counter = registers.program_counter as usize;
+
+    print!("PC: 0x{:04x}", registers.program_counter);
+
+    if let Some(location) = machine.cpu.formatter.symbols.describe(registers.program_counter){
+
+        print!(" <{}>", location);
+
+    }
+
+    print!("  SP: 0x{:04x}  Memory at PC:", registers.stack_pointer);
+
+    for offset in 0..3{
+
+        if let Some(byte) = machine.cpu.memory.get(program_counter + offset){
+
+            print!(" {:02x}", byte);
+
+        }
+
+    }
+
+    if let Some(instruction) = machine.cpu.memory.get(program_counter..).and_then(|bytes| disassembler::decode(bytes, registers.program_counter)){
+
+        print!("  ({})", machine.cpu.formatter.format(&instruction));
+
+    }
+
+    println!("\nA: 0x{:02x}  B: 0x{:02x}  C: 0x{:02x}  D: 0x{:02x}  E: 0x{:02x}  H: 0x{:02x}  L: 0x{:02x}  Flags: {:08b} (S Z 0 AC 0 P 1 C)",
+        registers.a, registers.b, registers.c, registers.d, registers.e, registers.h, registers.l, registers.flags
+    );
+
+}
+
+fn display_memory(machine: &Machine, arguments: &[&str]){
+
+    let start = match arguments.first().map(|address| machine.cpu.formatter.symbols.resolve(address)) {
+
+        Some(Ok(address)) => address as usize,
+
+        Some(Err(error)) => {
+
+            println!("{}", error);
+
+            return;
+
+        },
+
+        None => {
+
+            println!("Usage: memory <address> [length]");
+
+            return;
+
+        },
+
+    };
+
+    let length = arguments.get(1).and_then(|length| length.parse::<usize>().ok()).unwrap_or(16);
+
+    let end = (start + length).min(machine.cpu.memory.len());
+
+    for row_start in (start..end).step_by(16){
+
+        print!("{:04x}:", row_start);
+
+        for byte in &machine.cpu.memory[row_start..end.min(row_start + 16)]{
+
+            print!(" {:02x}", byte);
+
+        }
+
+        println!();
+
+    }
+
+}
+
+fn display_disassembly(machine: &Machine, arguments: &[&str]){
+
+    let mut address = match arguments.first().map(|address| machine.cpu.formatter.symbols.resolve(address)) {
+
+        Some(Ok(address)) => address as usize,
+
+        Some(Err(error)) => {
+
+            println!("{}", error);
+
+            return;
+
+        },
+
+        None => machine.cpu.registers().program_counter as usize,
+
+    };
+
+    let count = arguments.get(1).and_then(|count| count.parse::<usize>().ok()).unwrap_or(16);
+
+    for _ in 0..count{
+
+        let instruction = match machine.cpu.memory.get(address..).and_then(|bytes| disassembler::decode(bytes, address as u16)) {
+            Some(instruction) => instruction,
+            None => break, // The end of memory
+        };
+
+        let cycles = match instruction.branch_cycles {
+
+            Some(branch_cycles) => format!("{}/{}", instruction.cycles, branch_cycles),
+
+            None => instruction.cycles.to_string(),
+
+        };
+
+        if let Some(name) = machine.cpu.formatter.symbols.name(instruction.address){
+
+            println!("{}:", name);
+
+        }
+
+        println!("{:04x}  {:<16} ; {} cycles", instruction.address, machine.cpu.formatter.format(&instruction), cycles);
+
+        if !instruction.falls_through(){
+
+            println!(); // Separate blocks of code that control cannot fall through
+
+        }
+
+        address += instruction.length as usize;
+
+    }
+
+}
+
+// Writes an inclusive range of memory to a file, as Intel HEX if the name ends in .hex and raw binary otherwise
+fn save_memory(machine: &Machine, arguments: &[&str]) -> Result<String, String>{
+
+    let (file_name, start, end) = match arguments {
+
+        [file_name, start, end] => {
+
+            let symbols = &machine.cpu.formatter.symbols;
+
+            (*file_name, symbols.resolve(start)? as usize, symbols.resolve(end)? as usize)
+
+        },
+
+        _ => return Err("Usage: save <file> <start> <end>".to_string()),
+
+    };
+
+    if end < start || end >= machine.cpu.memory.len(){
+
+        return Err(format!("Invalid range {:04x}-{:04x}, memory ends at {:04x}", start, end, machine.cpu.memory.len() - 1));
+
+    }
+
+    let bytes = machine.cpu.memory[start..=end].to_vec();
+
+    let mut output = vec![];
+
+    if file_name.to_lowercase().ends_with(".hex"){
+
+        intel_hex::write(&[Segment{ address: start as u16, bytes }], &mut output).map_err(|error| error.to_string())?;
+
+    }
+    else{
+
+        output = bytes;
+
+    }
+
+    std::fs::write(file_name, output).map_err(|_| format!("Unable to write '{}'", file_name))?;
+
+    Ok(format!("Saved {:04x}-{:04x} to {}", start, end, file_name))
+
+}
+
+fn display_help(){
+
+    println!("\
+        Debugger Commands:\n\n\t\
+            continue / c                -> Resume emulation\n\t\
+            step / s [count]            -> Execute one or more instructions\n\t\
+            registers / r               -> Display the registers\n\t\
+            memory / m <address> [len]  -> Display memory from the given address or symbol\n\t\
+            disassemble / u [addr] [n]  -> Disassemble instructions from the given address, symbol or PC\n\t\
+            history [count]             -> Display the last instructions executed\n\t\
+            backtrace / bt              -> Display the calls that led to the current instruction\n\t\
+            syntax <intel|zilog>        -> Display instructions with Intel or Zilog mnemonics\n\t\
+            save <file> <start> <end>   -> Save memory to a binary file, or Intel HEX if the name ends in .hex\n\t\
+            break / b <address>         -> Break when the given address or symbol is executed\n\t\
+            watch / w <watchpoint>      -> Add a watchpoint, e.g. write:20f8-20f9=05:break\n\t\
+            list / l                    -> List watchpoints\n\t\
+            delete / d <index>          -> Delete a watchpoint\n\t\
+            quit / q                    -> Close the emulator
+    ");
+
+}
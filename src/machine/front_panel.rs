@@ -0,0 +1,459 @@
+// A front panel in the style of the Altair 8800's, drawn in the machine's window
+// Rows from the top: status lights, data lights D7-D0, address lights A15-A0, address and data switches 15-0,
+// then the control switches STOP, RUN, SINGLE STEP, EXAMINE, EXAMINE NEXT, DEPOSIT, DEPOSIT NEXT and RESET
+// Switches are flipped by clicking them or from the keyboard: hexadecimal digits are shifted into the address and data switches
+// from the right, Backspace clears them and F1 to F8 are the control switches in order
+use crate::machine::*;
+use crate::cpu::io_map::Device;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+pub const WIDTH: u32 = 464;
+pub const HEIGHT: u32 = 200;
+
+const MARGIN: i32 = 16;
+const SPACING: i32 = 28; // Between columns
+const LIGHT_SIZE: u32 = 12;
+const STATUS_ROW: i32 = 16;
+const DATA_ROW: i32 = 48;
+const ADDRESS_ROW: i32 = 80;
+const SWITCH_ROW: i32 = 112;
+const CONTROL_ROW: i32 = 160;
+const SWITCH_HEIGHT: u32 = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control{
+    Stop,
+    Run,
+    SingleStep,
+    Examine,
+    ExamineNext,
+    Deposit,
+    DepositNext,
+    Reset,
+}
+
+const CONTROLS: [Control; 8] = [
+    Control::Stop, Control::Run, Control::SingleStep, Control::Examine,
+    Control::ExamineNext, Control::Deposit, Control::DepositNext, Control::Reset,
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Lights{
+    pub status: [bool; 12], // INTE, PROT, MEMR, INP, M1, OUT, HLTA, STACK, WO, INT, WAIT and HLDA, left to right
+    pub data: u8,
+    pub address: u16,
+}
+
+#[derive(Default)]
+pub struct FrontPanel{
+    switches: Rc<Cell<u16>>, // Address and data switches, shared with the sense switches
+}
+
+impl FrontPanel{
+
+    pub fn switches(&self) -> u16{
+
+        self.switches.get()
+
+    }
+
+    pub fn set_switches(&self, value: u16){
+
+        self.switches.set(value);
+
+    }
+
+    // A device reading the upper eight switches, which programs use as the sense switches
+    pub fn sense_switches(&self) -> SenseSwitches{
+
+        SenseSwitches{ switches: Rc::clone(&self.switches) }
+
+    }
+
+    // Examine and deposit only work while the CPU is stopped, as on the Altair
+    pub fn operate(&self, control: Control, cpu: &mut Processor8080, ports: &mut Vec<u8>, audio_controller: &mut AudioController, running: &mut bool){
+
+        let address = cpu.registers().program_counter;
+
+        let value = self.switches() as u8; // Data is set on the lower eight switches
+
+        match control {
+
+            Control::Stop => *running = false,
+
+            Control::Run => {
+
+                cpu.resume();
+
+                *running = true;
+
+            },
+
+            Control::Reset => cpu.reset(),
+
+            _ if *running => {},
+
+            Control::SingleStep => cpu.emulate(ports, audio_controller),
+
+            Control::Examine => cpu.jump(self.switches()),
+
+            Control::ExamineNext => cpu.jump(address.wrapping_add(1)),
+
+            Control::Deposit => cpu.deposit(address, value),
+
+            Control::DepositNext => {
+
+                cpu.jump(address.wrapping_add(1));
+
+                cpu.deposit(address.wrapping_add(1), value);
+
+            },
+
+        }
+
+    }
+
+    // The buses show the address of the next instruction and the byte there, as they do when the CPU is stopped
+    // The status lights are those of fetching it, along with the interrupt enable, memory protect and wait lights
+    pub fn lights(&self, cpu: &Processor8080, running: bool) -> Lights{
+
+        let address = cpu.registers().program_counter;
+
+        let data = cpu.examine(address);
+
+        Lights{
+            status: [
+                cpu.interrupts_enabled(), // INTE
+                cpu.is_read_only(address), // PROT
+                true, // MEMR
+                false, // INP
+                true, // M1
+                false, // OUT
+                cpu.halted(), // HLTA
+                false, // STACK
+                true, // WO, lit when the CPU is not writing
+                false, // INT
+                !running, // WAIT
+                false, // HLDA
+            ],
+            data,
+            address,
+        }
+
+    }
+
+}
+
+pub struct SenseSwitches{
+    switches: Rc<Cell<u16>>,
+}
+
+impl Device for SenseSwitches{
+
+    fn input(&mut self, _port: u8) -> u8{
+
+        (self.switches.get() >> 8) as u8
+
+    }
+
+    fn output(&mut self, _port: u8, _value: u8, _memory: &mut [u8]){}
+
+}
+
+fn column_x(column: i32) -> i32{
+
+    MARGIN + column * SPACING
+
+}
+
+enum Target{
+    Switch(u8), // Bit number
+    Control(Control),
+}
+
+fn target_at(x: i32, y: i32) -> Option<Target>{
+
+    let column = (x - MARGIN).div_euclid(SPACING);
+
+    if x < MARGIN || x >= column_x(column) + LIGHT_SIZE as i32{
+
+        return None;
+
+    }
+
+    if (SWITCH_ROW..SWITCH_ROW + SWITCH_HEIGHT as i32).contains(&y) && column < 16{
+
+        return Some(Target::Switch(15 - column as u8));
+
+    }
+
+    if (CONTROL_ROW..CONTROL_ROW + SWITCH_HEIGHT as i32).contains(&y) && column % 2 == 0{
+
+        return CONTROLS.get(column as usize / 2).map(|control| Target::Control(*control));
+
+    }
+
+    None
+
+}
+
+pub fn key_event(machine: &mut Machine){
+
+    let mut event_pump = machine.sdl_context.event_pump().expect("Failed to retrieve event pump");
+
+    let mut controls = vec![];
+
+    for event in event_pump.poll_iter(){
+
+        let panel = match &machine.front_panel {
+            Some(panel) => panel,
+            None => return,
+        };
+
+        match event{
+
+            Event::Quit {..} |
+
+            Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+
+                machine.cpu.flush_output();
+
+                machine.audio_controller.close();
+
+                std::process::exit(0);
+
+            },
+
+            Event::KeyDown { keycode: Some(Keycode::F9), .. } => machine.cpu.request_break("Break key pressed".to_string()),
+
+            Event::KeyDown { keycode: Some(Keycode::P), .. } => machine.cpu.write_profile(),
+
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => panel.set_switches(0),
+
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+
+                let name = keycode.name();
+
+                let control = ["F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8"].iter().position(|key| *key == name);
+
+                if let Some(index) = control{
+
+                    controls.push(CONTROLS[index]);
+
+                }
+                else if let Some(digit) = name.chars().next().filter(|_| name.len() == 1).and_then(|character| character.to_digit(16)){
+
+                    panel.set_switches((panel.switches() << 4) | digit as u16);
+
+                }
+
+            },
+
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => match target_at(x, y) {
+
+                Some(Target::Switch(bit)) => panel.set_switches(panel.switches() ^ (1 << bit)),
+
+                Some(Target::Control(control)) => controls.push(control),
+
+                None => {},
+
+            },
+
+            _ => {},
+
+        }
+
+    }
+
+    if let Some(panel) = &machine.front_panel{
+
+        for control in controls{
+
+            panel.operate(control, &mut machine.cpu, &mut machine.ports, &mut machine.audio_controller, &mut machine.running);
+
+        }
+
+    }
+
+}
+
+pub fn draw(machine: &mut Machine){
+
+    let panel = match &machine.front_panel {
+        Some(panel) => panel,
+        None => return,
+    };
+
+    let lights = panel.lights(&machine.cpu, machine.running);
+
+    let switches = panel.switches();
+
+    let canvas = &mut machine.canvas;
+
+    canvas.set_draw_color(Color::RGB(40, 40, 48));
+
+    canvas.clear();
+
+    let mut light = |column: i32, row: i32, on: bool|{
+
+        canvas.set_draw_color(if on { Color::RGB(255, 40, 40) } else { Color::RGB(70, 10, 10) });
+
+        canvas.fill_rect(Rect::new(column_x(column), row, LIGHT_SIZE, LIGHT_SIZE)).expect("Failed to draw window");
+
+    };
+
+    for (column, on) in lights.status.iter().enumerate(){
+
+        light(column as i32, STATUS_ROW, *on);
+
+    }
+
+    for bit in 0..8{
+
+        light(15 - bit, DATA_ROW, (lights.data >> bit) & 1 != 0);
+
+    }
+
+    for bit in 0..16{
+
+        light(15 - bit, ADDRESS_ROW, (lights.address >> bit) & 1 != 0);
+
+    }
+
+    // Switches are drawn as a slot with the lever at the top when on
+    let mut switch = |column: i32, row: i32, up: bool|{
+
+        canvas.set_draw_color(Color::RGB(20, 20, 20));
+
+        canvas.fill_rect(Rect::new(column_x(column), row, LIGHT_SIZE, SWITCH_HEIGHT)).expect("Failed to draw window");
+
+        canvas.set_draw_color(Color::RGB(220, 220, 220));
+
+        let lever_y = if up { row } else { row + (SWITCH_HEIGHT - LIGHT_SIZE) as i32 };
+
+        canvas.fill_rect(Rect::new(column_x(column), lever_y, LIGHT_SIZE, LIGHT_SIZE)).expect("Failed to draw window");
+
+    };
+
+    for bit in 0..16{
+
+        switch(15 - bit, SWITCH_ROW, (switches >> bit) & 1 != 0);
+
+    }
+
+    for (index, control) in CONTROLS.iter().enumerate(){
+
+        let up = *control == Control::Run && machine.running || *control == Control::Stop && !machine.running;
+
+        switch(index as i32 * 2, CONTROL_ROW, up);
+
+    }
+
+    canvas.present();
+
+}
+
+#[cfg(test)]
+mod tests{
+
+    use super::*;
+
+    #[test]
+    fn toggle_in_and_step_a_program(){
+
+        let mut cpu = Processor8080::new(test::test_in, test::test_out, false);
+
+        cpu.initialize(vec![], 0x10000);
+
+        let panel = FrontPanel::default();
+
+        let mut ports = vec![0; 256];
+
+        let mut audio_controller = AudioController::default();
+
+        let mut running = true;
+
+        let mut operate = |cpu: &mut Processor8080, control: Control, switches: u16|{
+
+            panel.set_switches(switches);
+
+            panel.operate(control, cpu, &mut ports, &mut audio_controller, &mut running);
+
+        };
+
+        operate(&mut cpu, Control::Examine, 0); // Ignored while running
+
+        operate(&mut cpu, Control::Stop, 0);
+
+        operate(&mut cpu, Control::Examine, 0x0000);
+
+        for (index, byte) in [0x3E, 0x42, 0x32, 0x10, 0x00].iter().enumerate(){ // MVI A,42H then STA 0010H
+
+            operate(&mut cpu, if index == 0 { Control::Deposit } else { Control::DepositNext }, *byte);
+
+        }
+
+        operate(&mut cpu, Control::Reset, 0);
+
+        operate(&mut cpu, Control::SingleStep, 0);
+
+        operate(&mut cpu, Control::SingleStep, 0);
+
+        operate(&mut cpu, Control::Examine, 0x0010);
+
+        let lights = panel.lights(&cpu, false);
+
+        assert_eq!((lights.address, lights.data), (0x0010, 0x42));
+
+        assert_eq!(cpu.registers().a, 0x42);
+
+        panel.set_switches(0xA500);
+
+        assert_eq!(panel.sense_switches().input(0), 0xA5);
+
+    }
+
+    #[test]
+    fn halt_light(){
+
+        let mut cpu = Processor8080::new(test::test_in, test::test_out, false);
+
+        cpu.initialize(vec![], 0x10000);
+
+        cpu.deposit(0x0000, 0x76); // HLT
+
+        let panel = FrontPanel::default();
+
+        let mut ports = vec![0; 256];
+
+        let mut audio_controller = AudioController::default();
+
+        let mut running = false;
+
+        let hlta = |cpu: &Processor8080| panel.lights(cpu, false).status[6];
+
+        assert!(!hlta(&cpu)); // Only lit once the HLT has been executed
+
+        panel.operate(Control::SingleStep, &mut cpu, &mut ports, &mut audio_controller, &mut running);
+
+        assert!(hlta(&cpu));
+
+        panel.operate(Control::SingleStep, &mut cpu, &mut ports, &mut audio_controller, &mut running); // Idles
+
+        assert_eq!((cpu.registers().program_counter, hlta(&cpu)), (0x0001, true));
+
+        panel.operate(Control::Run, &mut cpu, &mut ports, &mut audio_controller, &mut running);
+
+        assert!(!hlta(&cpu));
+
+    }
+
+}
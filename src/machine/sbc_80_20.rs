@@ -0,0 +1,76 @@
+// The Intel iSBC 80/20 single board computer, with its terminal on an 8251 USART at ports ECH (data) and EDH (control)
+// and an 8253 interval timer at ports DCH to DFH, clocked at 1.0752MHz, about one pulse every two CPU cycles
+// Interrupts go through an 8259 controller at ports D8H and D9H, with the timer's counters 0 and 1 jumpered to IR2 and IR3
+// Two 8255 parallel interfaces are at ports E4H to E7H and E8H to EBH
+// ROM sockets fill 0000H to 0FFFH, where the monitor is loaded with --rom, and 2KiB of RAM ends at 3FFFH, the -4 board's 4KiB with --ram 4
+use crate::machine::*;
+use crate::devices::pic::Pic;
+use crate::devices::pit::Pit;
+use crate::devices::ppi::{Ppi, Unconnected};
+use crate::devices::usart::Usart;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const RAM_END: usize = 0x4000;
+const RAM_SIZE: usize = 0x0800;
+const ROM_END: usize = 0x1000;
+
+// Fails if the console's port can't be listened on
+pub fn setup(setup_config: &mut SetupConfiguration) -> Result<(), String>{
+
+    println!("\n\n\n\
+        Machine Interaction:\n\n\t\
+            Escape Key  -> Close Emulator\n\t\
+            B Key       -> Break Into Debugger\n\t\
+            P Key       -> Write Profile\n\n\
+        Load the monitor with --rom <file>, it starts at 0000H\n\
+    ");
+
+    setup_config.key_event_handler = test::key_event;
+
+    setup_config.interrupt_handler = test::test_interrupt; // Devices interrupt through the I/O map
+
+    setup_config.drawer = test::draw;
+
+    setup_config.memory_size = 0x10000;
+
+    let ram_start = RAM_END - setup_config.ram_size.unwrap_or(RAM_SIZE).min(RAM_END - ROM_END);
+
+    setup_config.read_only.push(0..ram_start); // ROM sockets, and no memory between them and the RAM
+
+    setup_config.read_only.push(RAM_END..0x10000);
+
+    let console = setup_config.console()?;
+
+    setup_config.io.attach(0xEC..=0xED, Box::new(Usart::new(console)));
+
+    setup_config.io.attach(0xE4..=0xE7, Box::new(Ppi::new(Box::new(Unconnected)))); // Parallel ports, with nothing connected
+
+    setup_config.io.attach(0xE8..=0xEB, Box::new(Ppi::new(Box::new(Unconnected))));
+
+    let pic = Rc::new(RefCell::new(Pic::new()));
+
+    let mut pit = Pit::new(2); // Counter 2 sets the USART's baud rate, which makes no difference here
+
+    for (counter, line) in [(0, 2), (1, 3)].iter(){
+
+        let pic = Rc::clone(&pic);
+
+        let line = *line;
+
+        pit.on_output[*counter] = Some(Box::new(move |level| pic.borrow_mut().request(line, level)));
+
+    }
+
+    setup_config.io.attach(0xD8..=0xD9, Box::new(pic));
+
+    setup_config.io.attach(0xDC..=0xDF, Box::new(pit));
+
+    setup_config.window.set_title("Intel iSBC 80/20").expect("Failed to set window title");
+
+    setup_config.window.set_size(256, 64).expect("Failed to size window");
+
+    Ok(())
+
+}
@@ -0,0 +1,50 @@
+// The Intel SDK-80 system design kit, a single board 8080 computer with its terminal on an 8251 USART at ports ECH (data) and EDH (control)
+// ROM sockets fill 0000H to 0FFFH, where the monitor is loaded with --rom, and 1KiB of RAM starts at 1000H, more can be fitted with --ram
+// Two 8255 parallel interfaces are at ports E4H to E7H and E8H to EBH
+use crate::machine::*;
+use crate::devices::ppi::{Ppi, Unconnected};
+use crate::devices::usart::Usart;
+
+const RAM_ADDRESS: usize = 0x1000;
+const RAM_SIZE: usize = 0x0400;
+
+// Fails if the console's port can't be listened on
+pub fn setup(setup_config: &mut SetupConfiguration) -> Result<(), String>{
+
+    println!("\n\n\n\
+        Machine Interaction:\n\n\t\
+            Escape Key  -> Close Emulator\n\t\
+            B Key       -> Break Into Debugger\n\t\
+            P Key       -> Write Profile\n\n\
+        Load the monitor with --rom <file>, it starts at 0000H\n\
+    ");
+
+    setup_config.key_event_handler = test::key_event;
+
+    setup_config.interrupt_handler = test::test_interrupt; // The USART's ready pins aren't wired to interrupt the CPU
+
+    setup_config.drawer = test::draw;
+
+    setup_config.memory_size = 0x10000;
+
+    let ram_end = (RAM_ADDRESS + setup_config.ram_size.unwrap_or(RAM_SIZE)).min(0x10000);
+
+    setup_config.read_only.push(0..RAM_ADDRESS); // Unused ROM sockets
+
+    setup_config.read_only.push(ram_end..0x10000);
+
+    let console = setup_config.console()?;
+
+    setup_config.io.attach(0xEC..=0xED, Box::new(Usart::new(console)));
+
+    setup_config.io.attach(0xE4..=0xE7, Box::new(Ppi::new(Box::new(Unconnected)))); // Parallel ports, with nothing connected
+
+    setup_config.io.attach(0xE8..=0xEB, Box::new(Ppi::new(Box::new(Unconnected))));
+
+    setup_config.window.set_title("Intel SDK-80").expect("Failed to set window title");
+
+    setup_config.window.set_size(256, 64).expect("Failed to size window");
+
+    Ok(())
+
+}
@@ -11,6 +11,7 @@ pub fn setup(setup_config: &mut SetupConfiguration){
     println!("\n\n\n\
         Machine Interaction:\n\n\t\
             Escape Key  -> Close Emulator\n\t\
+            B Key       -> Break Into Debugger\n\t\
             T Key       -> Tilt Machine\n\t\
             C Key       -> Input Coin\n\t\
             1 Key       -> Player 1 Ready\n\t\
@@ -34,28 +35,34 @@ pub fn setup(setup_config: &mut SetupConfiguration){
 
     setup_config.drawer = draw;
 
+    setup_config.mirror_ram = true;
+
     setup_config.files.push(FileToLoad{
         name: "space-invaders-source/SpaceInvaders.h".to_string(),
         offset: 0x0,
-        size: 0x800
+        size: 0x800,
+        read_only: true,
     });
 
     setup_config.files.push(FileToLoad{
         name: "space-invaders-source/SpaceInvaders.g".to_string(),
         offset: 0x800,
-        size: 0x800
+        size: 0x800,
+        read_only: true,
     });
 
     setup_config.files.push(FileToLoad{
         name: "space-invaders-source/SpaceInvaders.f".to_string(),
         offset: 0x1000,
-        size: 0x800
+        size: 0x800,
+        read_only: true,
     });
 
     setup_config.files.push(FileToLoad{
         name: "space-invaders-source/SpaceInvaders.e".to_string(),
         offset: 0x1800,
-        size: 0x800
+        size: 0x800,
+        read_only: true,
     });
 
     let track_names = vec![
@@ -104,6 +111,10 @@ fn key_event(machine: &mut Machine){
                 }
             },
 
+            Event::KeyDown { keycode: Some(Keycode::B), .. } => machine.cpu.request_break("Break key pressed".to_string()),
+
+            Event::KeyDown { keycode: Some(Keycode::P), .. } => machine.cpu.write_profile(),
+
             Event::KeyDown { keycode: Some(Keycode::Up), .. } => machine.audio_controller.volume_up(),
 
             Event::KeyDown { keycode: Some(Keycode::Down), .. } => machine.audio_controller.volume_down(),
@@ -114,7 +125,7 @@ fn key_event(machine: &mut Machine){
     
             Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
 
-                machine.cpu.logger.flush().expect("Failed to flush output buffer");
+                machine.cpu.flush_output();
 
                 machine.audio_controller.close();
 
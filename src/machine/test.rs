@@ -16,11 +16,15 @@ pub fn key_event(machine: &mut Machine){
                 }
             },
 
+            Event::KeyDown { keycode: Some(Keycode::B), .. } => machine.cpu.request_break("Break key pressed".to_string()),
+
+            Event::KeyDown { keycode: Some(Keycode::P), .. } => machine.cpu.write_profile(),
+
             Event::Quit {..} |
     
             Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
 
-                machine.cpu.logger.flush().expect("Failed to flush output buffer");
+                machine.cpu.flush_output();
 
                 machine.audio_controller.close();
 
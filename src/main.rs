@@ -5,32 +5,71 @@
 
 mod machine;
 mod cpu;
+mod asm;
+mod intel_hex;
+mod cpm;
+mod devices;
+#[cfg(test)]
+mod exerciser;
+
+use cpu::FileToLoad;
+use cpu::coverage::Coverage;
+use cpu::disassembler::{analysis, graph, listing, Formatter, Syntax};
+use cpu::disassembler::symbols::SymbolTable;
+use cpu::trace::{Trace, TraceCondition};
+use cpu::watchpoint::Watchpoint;
 
 use std::io;
 use std::env;
 
-const NUMBER_OF_PROGRAMS_EMULATED: u8 = 1;
+const NUMBER_OF_PROGRAMS_EMULATED: u8 = 5;
+
+// A command line tool run instead of the emulator, e.g. emulator_8080 disasm
+type Tool = fn(&[String]) -> Result<(), String>;
 
 fn main() {
 
-    let (test, log_to_file) = {
+    let args: Vec<String> = env::args().collect();
+
+    let tool: Option<Tool> = match args.get(1).map(|command| command.as_str()) {
+        Some("disasm") => Some(disassemble),
+        Some("asm") => Some(assemble),
+        Some("cpm") => Some(run_cpm),
+        _ => None,
+    };
+
+    if let Some(tool) = tool{
+
+        if let Err(error) = tool(&args[2..]){
+
+            println!("{}", error);
+
+        }
+
+        return;
+
+    }
+
+    let options = match parse_options(&args) {
+
+        Ok(options) => options,
+
+        Err(error) => {
 
-        let args: Vec<String> = env::args().collect();
+            println!("{}", error);
 
-        let test = args.contains(&"-t".to_string()) || args.contains(&"--test".to_string());
+            return;
 
-        (
-            test,
-            test && (args.contains(&"-l".to_string()) || args.contains(&"--log-to-file".to_string())),
-        )
+        },
 
     };
 
-    if test{
+    if options.test{
     
-        let mut arcade_machine = machine::Machine::new(0, log_to_file, test); // Params: Game ID, Log To File, Test
-    
-        arcade_machine.start();
+        match machine::Machine::new(0, options) { // Params: Game ID, Options
+            Ok(mut arcade_machine) => arcade_machine.start(),
+            Err(error) => println!("{}", error),
+        }
 
     }
     else{
@@ -51,9 +90,10 @@ fn main() {
     
         if result.is_ok(){
     
-            let mut arcade_machine = machine::Machine::new(result.unwrap(), false, false); // Params: Game ID, Log To File, Test
-        
-            arcade_machine.start();
+            match machine::Machine::new(result.unwrap(), options) { // Params: Game ID, Options
+                Ok(mut arcade_machine) => arcade_machine.start(),
+                Err(error) => println!("{}", error),
+            }
     
         }
         else{
@@ -66,6 +106,427 @@ fn main() {
 
 }
 
+const DISASSEMBLE_USAGE: &str = "Usage: emulator_8080 disasm <rom...> [--org <address>] [--linear] [--entry <address>]... [--jump-table <address>:<entries>]... [--symbols <file>] [--coverage <file>] [--zilog] [--dot] [--routine <address>]";
+
+// ROMs are loaded one after another starting at the origin address, Intel HEX files are loaded at the addresses they contain
+// The origin defaults to the lowest address loaded
+// --dot writes a control flow graph in Graphviz DOT format instead of a listing, for the whole ROM or one routine
+// Code is found by tracing execution from the entry points unless --linear is given, all other bytes are listed as data
+// Code symbols are traced as entry points and data symbols are listed using their type
+// The instructions a coverage map from a run shows were executed are traced as entry points
+fn disassemble(args: &[String]) -> Result<(), String>{
+
+    let mut origin = None;
+
+    let mut rom_names = vec![];
+
+    let mut linear = false;
+
+    let mut entry_points = vec![];
+
+    let mut jump_tables = vec![];
+
+    let mut symbols = SymbolTable::default();
+
+    let mut coverage = None;
+
+    let mut syntax = Syntax::Intel;
+
+    let mut dot = false;
+
+    let mut routine = None;
+
+    let mut arguments = args.iter();
+
+    while let Some(argument) = arguments.next(){
+
+        let mut value = || arguments.next().ok_or(format!("Missing value for {}", argument));
+
+        match argument.as_str() {
+
+            "--org" => origin = Some(cpu::parse_address(value()?)?),
+
+            "--linear" => linear = true,
+
+            "--entry" => entry_points.push(cpu::parse_address(value()?)?),
+
+            "--jump-table" => jump_tables.push(analysis::JumpTable::parse(value()?)?),
+
+            "--symbols" => symbols = SymbolTable::load(value()?)?,
+
+            "--coverage" => coverage = Some(Coverage::load(value()?)?),
+
+            "--zilog" => syntax = Syntax::Zilog,
+
+            "--dot" => dot = true,
+
+            "--routine" => {
+                routine = Some(value()?.clone());
+                dot = true;
+            },
+
+            _ if argument.starts_with('-') => return Err(DISASSEMBLE_USAGE.to_string()),
+
+            _ => rom_names.push(argument.clone()),
+
+        }
+
+    }
+
+    if rom_names.is_empty(){
+
+        return Err(DISASSEMBLE_USAGE.to_string());
+
+    }
+
+    let mut memory = vec![0; 0x10000];
+
+    let mut offset = origin.unwrap_or(0) as usize;
+
+    let mut loaded = None; // Lowest and highest address loaded
+
+    for name in rom_names{
+
+        let is_hex = name.to_lowercase().ends_with(".hex");
+
+        let file = FileToLoad{
+            name,
+            offset: if is_hex { 0 } else { offset }, // Intel HEX files hold their own addresses
+            size: 0,
+            read_only: true,
+        };
+
+        for segment in file.read()?{
+
+            let start = segment.address as usize;
+
+            let end = start + segment.bytes.len();
+
+            memory[start..end].copy_from_slice(&segment.bytes);
+
+            loaded = match loaded {
+                Some((lowest, highest)) => Some((start.min(lowest), end.max(highest))),
+                None => Some((start, end)),
+            };
+
+            if !is_hex{
+
+                offset = end;
+
+            }
+
+        }
+
+    }
+
+    let (lowest, end) = loaded.ok_or_else(|| "No bytes were loaded".to_string())?;
+
+    let origin = origin.unwrap_or(lowest as u16);
+
+    if origin as usize >= end{
+
+        return Err(format!("Nothing was loaded at or after the origin {:04x}", origin));
+
+    }
+
+    let rom = &memory[origin as usize..end];
+
+    let routine = match routine {
+        Some(routine) => Some(symbols.resolve(&routine)?),
+        None => None,
+    };
+
+    let lines = if linear{
+
+        entry_points.push(origin);
+
+        listing::linear_sweep(rom, origin)
+
+    }
+    else{
+
+        if origin == 0{
+
+            entry_points.extend_from_slice(&[0x00, 0x08, 0x10]); // Reset and interrupt vectors
+
+        }
+        else{
+
+            entry_points.push(origin);
+
+        }
+
+        let code_map = analysis::trace(rom, origin, &entry_points, &jump_tables, &symbols, coverage.as_ref());
+
+        analysis::lines_from_code_map(rom, &code_map, &jump_tables, &symbols)
+
+    };
+
+    let formatter = Formatter{
+        labels: listing::generate_labels(&lines),
+        symbols,
+        syntax,
+    };
+
+    if dot{
+
+        entry_points.extend(formatter.symbols.iter().map(|(address, _)| address));
+
+        let graph = graph::Graph::build(&lines, &entry_points);
+
+        return match routine {
+
+            Some(address) if !graph.blocks.contains_key(&address) => Err(format!("No code starts at {:04x}", address)),
+
+            Some(address) => {
+
+                let name = formatter.label(address).map(|name| name.to_string()).unwrap_or_else(|| format!("{:04X}", address));
+
+                graph.routine(address).write_dot(&name, &formatter, &mut io::stdout().lock()).map_err(|error| error.to_string())
+
+            },
+
+            None => graph.write_dot("ROM", &formatter, &mut io::stdout().lock()).map_err(|error| error.to_string()),
+
+        };
+
+    }
+
+    listing::write_listing(rom, origin, &lines, &formatter, &mut io::stdout().lock()).map_err(|error| error.to_string())
+
+}
+
+const ASSEMBLE_USAGE: &str = "Usage: emulator_8080 asm <source> [-o <output>] [--hex] [--symbols <file>]";
+
+// Writes a binary starting at the lowest address assembled, or Intel HEX with --hex
+// The output defaults to the source file name with a .bin or .hex extension
+fn assemble(args: &[String]) -> Result<(), String>{
+
+    let mut source_name = None;
+
+    let mut output_name = None;
+
+    let mut hex = false;
+
+    let mut symbols_name = None;
+
+    let mut arguments = args.iter();
+
+    while let Some(argument) = arguments.next(){
+
+        let mut value = || arguments.next().cloned().ok_or(format!("Missing value for {}", argument));
+
+        match argument.as_str() {
+
+            "-o" | "--output" => output_name = Some(value()?),
+
+            "--hex" => hex = true,
+
+            "--symbols" => symbols_name = Some(value()?),
+
+            _ if argument.starts_with('-') || source_name.is_some() => return Err(ASSEMBLE_USAGE.to_string()),
+
+            _ => source_name = Some(argument.clone()),
+
+        }
+
+    }
+
+    let source_name = source_name.ok_or_else(|| ASSEMBLE_USAGE.to_string())?;
+
+    let source = std::fs::read_to_string(&source_name).map_err(|_| format!("Unable to read '{}'", source_name))?;
+
+    let program = asm::assemble(&source).map_err(|error| format!("{}: {}", source_name, error))?;
+
+    let output_name = output_name.unwrap_or_else(|| {
+        std::path::Path::new(&source_name).with_extension(if hex { "hex" } else { "bin" }).to_string_lossy().into_owned()
+    });
+
+    let mut output = vec![];
+
+    if hex{
+
+        intel_hex::write(&program.segments, &mut output).map_err(|error| error.to_string())?;
+
+    }
+    else{
+
+        let (start, binary) = program.to_binary();
+
+        println!("Binary starts at address {:04X}", start);
+
+        output = binary;
+
+    }
+
+    std::fs::write(&output_name, output).map_err(|_| format!("Unable to write '{}'", output_name))?;
+
+    if let Some(symbols_name) = symbols_name{
+
+        let symbols: String = program.labels.iter().map(|(name, address)| format!("{:04X} {}\n", address, name)).collect();
+
+        std::fs::write(&symbols_name, symbols).map_err(|_| format!("Unable to write '{}'", symbols_name))?;
+
+    }
+
+    Ok(())
+
+}
+
+const CPM_USAGE: &str = "Usage: emulator_8080 cpm <program.com> [arguments...] [--dir <directory>]";
+
+// Runs a CP/M program with its console on standard input and output
+// Its files are the ones in the directory, which defaults to the one holding the program
+fn run_cpm(args: &[String]) -> Result<(), String>{
+
+    let mut program_name = None;
+
+    let mut program_arguments = vec![];
+
+    let mut directory = None;
+
+    let mut arguments = args.iter();
+
+    while let Some(argument) = arguments.next(){
+
+        match argument.as_str() {
+
+            "--dir" => directory = Some(arguments.next().ok_or(format!("Missing value for {}", argument))?.clone()),
+
+            _ if program_name.is_none() => program_name = Some(argument.clone()),
+
+            _ => program_arguments.push(argument.clone()),
+
+        }
+
+    }
+
+    let program_name = program_name.ok_or_else(|| CPM_USAGE.to_string())?;
+
+    let program = std::fs::read(&program_name).map_err(|_| format!("Unable to read '{}'", program_name))?;
+
+    let directory = match directory {
+        Some(directory) => std::path::PathBuf::from(directory),
+        None => std::path::Path::new(&program_name).parent().filter(|parent| !parent.as_os_str().is_empty()).map_or_else(|| ".".into(), |parent| parent.to_path_buf()),
+    };
+
+    let mut system = cpm::Cpm::new(&program, &program_arguments, directory, cpm::Console::standard())?;
+
+    system.run(None);
+
+    system.processor.flush_output();
+
+    Ok(())
+
+}
+
+fn parse_options(args: &[String]) -> Result<machine::Options, String>{
+
+    let test = args.contains(&"-t".to_string()) || args.contains(&"--test".to_string());
+
+    let symbols = match get_argument_values(args, &["-s", "--symbols"]).last() {
+        Some(file_name) => SymbolTable::load(file_name)?,
+        None => SymbolTable::default(),
+    };
+
+    let watchpoints = get_argument_values(args, &["-w", "--watch"]).iter()
+        .map(|specification| Watchpoint::parse(specification, &symbols))
+        .collect::<Result<Vec<Watchpoint>, String>>()?;
+
+    let trace = match get_argument_values(args, &["--trace"]).last() {
+
+        Some(file_name) => {
+
+            let mut trace = Trace::to_file(file_name)?;
+
+            if let Some(condition) = get_argument_values(args, &["--trace-start"]).last(){
+
+                trace.start = Some(TraceCondition::parse(condition, &symbols)?);
+
+            }
+
+            if let Some(condition) = get_argument_values(args, &["--trace-stop"]).last(){
+
+                trace.stop = Some(TraceCondition::parse(condition, &symbols)?);
+
+            }
+
+            for filter in get_argument_values(args, &["--trace-filter"]){
+
+                trace.filters.push(Trace::parse_filter(&filter, &symbols)?);
+
+            }
+
+            Some(trace)
+
+        },
+
+        None => None,
+
+    };
+
+    let mut files = vec![];
+
+    for file in get_argument_values(args, &["--rom"]){
+
+        files.push(FileToLoad::parse(&file, true)?);
+
+    }
+
+    for file in get_argument_values(args, &["--load"]){
+
+        files.push(FileToLoad::parse(&file, false)?);
+
+    }
+
+    let ram_size = match get_argument_values(args, &["--ram"]).last() {
+        Some(size) => Some(size.parse::<usize>().ok().filter(|size| (1..=64).contains(size)).ok_or(format!("Invalid RAM size '{}', give it in KiB from 1 to 64", size))? * 1024),
+        None => None,
+    };
+
+    let start_address = match get_argument_values(args, &["--start"]).last() {
+        Some(address) => Some(symbols.resolve(address)?),
+        None => None,
+    };
+
+    let serial_port = match get_argument_values(args, &["--serial-port"]).last() {
+        Some(port) => Some(port.parse::<u16>().map_err(|_| format!("Invalid serial port '{}'", port))?),
+        None => None,
+    };
+
+    let history_size = match get_argument_values(args, &["--history"]).last() {
+        Some(size) => Some(size.parse::<usize>().map_err(|_| format!("Invalid history size '{}'", size))?),
+        None => None,
+    };
+
+    Ok(machine::Options{
+        log_to_file: test && (args.contains(&"-l".to_string()) || args.contains(&"--log-to-file".to_string())),
+        test,
+        watchpoints,
+        symbols,
+        trace,
+        history_size,
+        profile: get_argument_values(args, &["--profile"]).last().cloned(),
+        coverage: get_argument_values(args, &["--coverage"]).last().cloned(),
+        disks: get_argument_values(args, &["--disk"]),
+        files,
+        ram_size,
+        start_address,
+        serial_port,
+    })
+
+}
+
+// Collects the value following every occurrence of the given flag
+fn get_argument_values(args: &[String], flags: &[&str]) -> Vec<String>{
+
+    args.windows(2)
+        .filter(|pair| flags.contains(&pair[0].as_str()))
+        .map(|pair| pair[1].clone())
+        .collect()
+
+}
+
 fn display_options(){
 
     print!("\
@@ -74,6 +535,10 @@ fn display_options(){
             Game ID     |     Game Name
         -----------------------------------
                 0       |  Space Invaders
+                1       |  CP/M 2.2
+                2       |  Altair 8800
+                3       |  Intel SDK-80
+                4       |  Intel iSBC 80/20
     \n\
     :>> \
     ");